@@ -0,0 +1,209 @@
+use crate::DecodedCode;
+use crate::RenderOptions;
+use crate::ScanError;
+use anyhow::bail;
+use anyhow::Result;
+use qrencode::bits::Bits;
+use qrencode::EcLevel;
+use qrencode::Version;
+
+/// The maximum number of codes a content can be split across.
+pub const MAX_PARTS: usize = 16;
+
+/// The position of a QR code in a structured append sequence, the codes a content
+/// is split across.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructuredAppend {
+    /// The position of the code in the sequence, from 0.
+    pub index: u8,
+
+    /// The number of codes in the sequence, up to [`MAX_PARTS`].
+    pub total: u8,
+
+    /// The XOR of all the bytes of the whole content, telling sequences apart.
+    pub parity: u8,
+}
+
+/// Split the content into the fewest parts of about the same size that each fit in
+/// a QR code of `max_version`, with their positions in the sequence.
+pub fn split_content<'a>(
+    content: &'a [u8],
+    opts: &RenderOptions,
+) -> Result<Vec<(&'a [u8], StructuredAppend)>> {
+    let capacity = part_capacity(opts.max_version, opts.raster_ec_level())?;
+    let count = content.len().div_ceil(capacity).max(1);
+    if count > MAX_PARTS {
+        bail!(
+            "the content needs {count} codes of version {}, above the maximum of {MAX_PARTS}",
+            opts.max_version
+        );
+    }
+
+    let chunks: Vec<_> = if content.is_empty() {
+        vec![content]
+    } else {
+        content.chunks(content.len().div_ceil(count)).collect()
+    };
+
+    let total = chunks.len() as u8;
+    let parity = parity(content);
+    let parts = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let part = StructuredAppend {
+                index: index as u8,
+                total,
+                parity,
+            };
+            (chunk, part)
+        })
+        .collect();
+
+    Ok(parts)
+}
+
+/// Join the parts of each structured append sequence into one code, in place of its
+/// first part and with its metadata. The other codes are kept as they are.
+///
+/// Fails with [`ScanError::IncompleteSequence`] if a part is missing.
+pub fn join_parts(codes: Vec<DecodedCode>) -> Result<Vec<DecodedCode>, ScanError> {
+    let mut joined: Vec<DecodedCode> = vec![];
+
+    // The position of each sequence in the joined codes, and its parts
+    let mut sequences: Vec<(usize, StructuredAppend, Vec<DecodedCode>)> = vec![];
+
+    for code in codes {
+        let Some(part) = code.structured_append else {
+            joined.push(code);
+            continue;
+        };
+
+        let sequence = sequences.iter_mut().find(|(_, first, _)| {
+            first.total == part.total && first.parity == part.parity
+        });
+        match sequence {
+            Some((_, _, parts)) => parts.push(code),
+            None => {
+                sequences.push((joined.len(), part, vec![code.clone()]));
+                joined.push(code);
+            }
+        }
+    }
+
+    for (position, first, mut parts) in sequences {
+        parts.sort_by_key(|code| code.structured_append.map(|part| part.index));
+        parts.dedup_by_key(|code| code.structured_append.map(|part| part.index));
+
+        if parts.len() != first.total as usize {
+            return Err(ScanError::IncompleteSequence {
+                found: parts.len(),
+                total: first.total as usize,
+            });
+        }
+
+        let content: Vec<u8> =
+            parts.iter().flat_map(|code| code.content.clone()).collect();
+        if parity(&content) != first.parity {
+            let err = "the parity of the joined content does not match";
+            return Err(ScanError::DecodeFailed(err.into()));
+        }
+
+        // With the metadata of the first part
+        let code = &mut joined[position];
+        *code = parts.swap_remove(0);
+        code.content = content;
+        code.structured_append = None;
+    }
+
+    Ok(joined)
+}
+
+/// The XOR of all the bytes.
+fn parity(content: &[u8]) -> u8 {
+    content.iter().fold(0, |parity, byte| parity ^ byte)
+}
+
+/// The width of the character count of byte mode segments.
+fn count_bits(version: i16) -> usize {
+    if version <= 9 {
+        8
+    } else {
+        16
+    }
+}
+
+/// The number of bytes that fit in a QR code of the given version, in one byte mode
+/// segment.
+pub(crate) fn byte_capacity(version: i16, ec_level: EcLevel) -> Result<usize> {
+    let bits = Bits::new(Version::Normal(version)).max_len(ec_level)?;
+    Ok(bits.saturating_sub(4 + count_bits(version)) / 8)
+}
+
+/// The number of bytes of a part that fit in a QR code of the given version.
+fn part_capacity(version: i16, ec_level: EcLevel) -> Result<usize> {
+    let bits = Bits::new(Version::Normal(version)).max_len(ec_level)?;
+
+    // The structured append header, then the byte mode header
+    let header = 4 + 16 + 4 + count_bits(version);
+    Ok(bits.saturating_sub(header) / 8)
+}
+
+/// The data codewords of the part in a QR code of the given version, or `None` if it
+/// does not fit.
+pub(crate) fn encode_part(
+    content: &[u8],
+    part: StructuredAppend,
+    version: i16,
+    ec_level: EcLevel,
+) -> Result<Option<Vec<u8>>> {
+    if content.len() > part_capacity(version, ec_level)? {
+        return Ok(None);
+    }
+    let capacity = Bits::new(Version::Normal(version)).max_len(ec_level)?;
+
+    let mut bits = BitWriter::default();
+    bits.push(4, 0b0011);
+    bits.push(4, part.index as u32);
+    bits.push(4, part.total.saturating_sub(1) as u32);
+    bits.push(8, part.parity as u32);
+    bits.push(4, 0b0100);
+    bits.push(count_bits(version), content.len() as u32);
+    for byte in content {
+        bits.push(8, *byte as u32);
+    }
+
+    // The terminator, then pad to whole bytes, then with the alternating pad bytes
+    bits.push(4.min(capacity - bits.len), 0);
+    bits.push((8 - bits.len % 8) % 8, 0);
+    for pad in [0xec, 0x11].into_iter().cycle() {
+        if bits.len >= capacity {
+            break;
+        }
+        bits.push(8, pad);
+    }
+
+    Ok(Some(bits.bytes))
+}
+
+/// Writes the data codewords of a QR code bit by bit.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, count: usize, value: u32) {
+        for i in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if value >> i & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::bail;
+use anyhow::Result;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+/// The modification date of the files, 1980-01-01 in MS-DOS format, the earliest there
+/// is. A fixed date keeps the archives of the same files identical.
+const DOS_DATE: u16 = 1 << 5 | 1;
+
+/// Compress the files, as their names and contents, into a zip archive.
+pub fn zip_files(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    if files.len() > u16::MAX as usize {
+        bail!("too many files for a zip archive: {}", files.len());
+    }
+
+    let mut zip = vec![];
+    let mut directory = vec![];
+    for (name, content) in files {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(content)?;
+        let compressed = encoder.finish()?;
+
+        let offset = zip.len();
+        let (Ok(offset), Ok(size), Ok(compressed_size), Ok(name_len)) = (
+            u32::try_from(offset),
+            u32::try_from(content.len()),
+            u32::try_from(compressed.len()),
+            u16::try_from(name.len()),
+        ) else {
+            bail!("{name}: too large for a zip archive");
+        };
+
+        // The fields the local header and the central directory have in common:
+        // version needed to extract, UTF-8 names flag, deflate, time and date, CRC-32,
+        // sizes and name length
+        let mut fields = vec![];
+        fields.extend(20u16.to_le_bytes());
+        fields.extend((1u16 << 11).to_le_bytes());
+        fields.extend(8u16.to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+        fields.extend(DOS_DATE.to_le_bytes());
+        fields.extend(crc32fast::hash(content).to_le_bytes());
+        fields.extend(compressed_size.to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend(name_len.to_le_bytes());
+
+        zip.extend(0x04034b50u32.to_le_bytes());
+        zip.extend(&fields);
+        zip.extend(0u16.to_le_bytes());
+        zip.extend(name.as_bytes());
+        zip.extend(&compressed);
+
+        directory.extend(0x02014b50u32.to_le_bytes());
+        directory.extend(20u16.to_le_bytes());
+        directory.extend(&fields);
+        // Extra field and comment lengths, disk number, and attributes
+        directory.extend([0; 12]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+
+    let (Ok(offset), Ok(size)) =
+        (u32::try_from(zip.len()), u32::try_from(directory.len()))
+    else {
+        bail!("too large for a zip archive");
+    };
+    let count = files.len() as u16;
+    zip.extend(directory);
+    zip.extend(0x06054b50u32.to_le_bytes());
+    zip.extend([0; 4]);
+    zip.extend(count.to_le_bytes());
+    zip.extend(count.to_le_bytes());
+    zip.extend(size.to_le_bytes());
+    zip.extend(offset.to_le_bytes());
+    zip.extend(0u16.to_le_bytes());
+    Ok(zip)
+}
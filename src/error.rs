@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// The reasons scanning an image can fail.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ScanError {
+    /// No code was found in the image.
+    NoCodeFound,
+    /// A code was found, but could not be decoded.
+    DecodeFailed(Box<dyn std::error::Error + Send + Sync>),
+    /// Some of the codes of a structured append sequence are missing.
+    IncompleteSequence { found: usize, total: usize },
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoCodeFound => write!(f, "no QR code found"),
+            Self::DecodeFailed(err) => {
+                write!(f, "found a QR code but failed to decode it: {err}")
+            }
+            Self::IncompleteSequence { found, total } => {
+                write!(f, "found only {found} of the {total} codes of the sequence")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::NoCodeFound | Self::IncompleteSequence { .. } => None,
+            Self::DecodeFailed(err) => Some(err.as_ref()),
+        }
+    }
+}
@@ -0,0 +1,1055 @@
+//! Scan and generate QR codes.
+//!
+//! ```no_run
+//! let image = image::open("/path/to/input.png").unwrap();
+//!
+//! for code in qrscan::scan_image(&image).unwrap() {
+//!     println!("{}", String::from_utf8_lossy(&code.content));
+//! }
+//!
+//! let png = qrscan::encode_to_png(b"hello", &Default::default()).unwrap();
+//! ```
+
+mod append;
+mod error;
+mod payload;
+mod render;
+mod scan;
+
+pub use append::join_parts;
+pub use append::split_content;
+pub use append::StructuredAppend;
+pub use append::MAX_PARTS;
+pub use error::ScanError;
+pub use payload::classify;
+pub use payload::parse_wifi;
+pub use payload::url_decode;
+pub use payload::ContentType;
+pub use payload::WifiNetwork;
+pub use qrencode::EcLevel;
+pub use render::build_binary_image;
+pub use render::encode_to_ascii;
+pub use render::encode_to_bmp;
+pub use render::encode_to_braille;
+pub use render::encode_to_eps;
+pub use render::encode_to_gif;
+pub use render::encode_to_jpeg;
+pub use render::encode_to_matrix;
+pub use render::encode_to_pdf;
+pub use render::encode_to_png;
+pub use render::encode_to_svg;
+pub use render::encode_to_tiff;
+pub use render::encode_to_unicode;
+pub use render::encode_to_webp;
+pub use render::ColorSpace;
+pub use render::Gradient;
+pub use render::ModuleStyle;
+pub use render::RenderOptions;
+pub use render::MAX_IMAGE_SIZE;
+pub use render::MAX_MODULE_SIZE;
+pub use scan::count_grids;
+pub use scan::profile_scan;
+pub use scan::read_metadata;
+pub use scan::scan_grids;
+pub use scan::scan_image;
+pub use scan::scan_image_failed;
+pub use scan::scan_image_with;
+pub use scan::Corrections;
+pub use scan::DecodedCode;
+pub use scan::FailedGrid;
+pub use scan::ScanOptions;
+pub use scan::ScanProfile;
+pub use scan::Symbology;
+pub use scan::Threshold;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_roundtrip() {
+        let png = encode_to_png(b"foo lib", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        let codes = scan_image(&image).unwrap();
+
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].content, b"foo lib");
+        assert_eq!(codes[0].content_str(), Some("foo lib"));
+        assert_eq!(codes[0].grid_size(), 21);
+    }
+
+    #[test]
+    fn test_png_dpi() {
+        let png = encode_to_png(b"foo", &Default::default()).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        assert!(decoder.read_info().unwrap().info().pixel_dims.is_none());
+
+        let opts = RenderOptions {
+            dpi: Some(300),
+            ..Default::default()
+        };
+        let png = encode_to_png(b"foo dpi", &opts).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let dims = decoder.read_info().unwrap().info().pixel_dims.unwrap();
+        assert_eq!((dims.xppu, dims.yppu), (11811, 11811));
+        assert_eq!(dims.unit, png::Unit::Meter);
+
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(scan_image(&image).unwrap()[0].content, b"foo dpi");
+    }
+
+    #[test]
+    fn test_lossless_roundtrip() {
+        let opts = Default::default();
+        let encoders = [encode_to_gif, encode_to_bmp, encode_to_tiff];
+
+        for encode in encoders {
+            let bytes = encode(b"foo lossless", &opts).unwrap();
+            let image = image::load_from_memory(&bytes).unwrap();
+            let codes = scan_image(&image).unwrap();
+
+            assert_eq!(codes[0].content, b"foo lossless");
+        }
+    }
+
+    #[test]
+    fn test_quiet_zone() {
+        for (quiet_zone, width) in [(0, 21), (4, 29), (10, 41)] {
+            let opts = RenderOptions {
+                module_size: 1,
+                quiet_zone,
+                ..Default::default()
+            };
+            let image = build_binary_image(b"foo", &opts).unwrap();
+            assert_eq!(image.width(), width);
+
+            let ascii = encode_to_ascii(b"foo", &opts).unwrap();
+            assert_eq!(ascii.lines().count() as u32, width);
+            assert!(ascii.is_ascii());
+        }
+    }
+
+    #[test]
+    fn test_size() {
+        for size in [58, 512, 1000] {
+            let opts = RenderOptions {
+                size: Some(size),
+                ..Default::default()
+            };
+            let image = build_binary_image(b"foo size", &opts).unwrap();
+            assert_eq!(image.dimensions(), (size, size));
+
+            let codes = scan_image(&image.into()).unwrap();
+            assert_eq!(codes[0].content, b"foo size");
+        }
+
+        let opts = RenderOptions {
+            size: Some(28),
+            ..Default::default()
+        };
+        assert!(build_binary_image(b"foo size", &opts).is_err());
+    }
+
+    #[test]
+    fn test_braille() {
+        let opts = Default::default();
+        let braille = encode_to_braille(b"foo", &opts, false).unwrap();
+
+        // 21 modules and a quiet zone of 4 on each side
+        let lines: Vec<_> = braille.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert!(lines.iter().all(|line| line.chars().count() == 15));
+        assert!(braille
+            .chars()
+            .all(|c| c == '\n' || ('\u{2800}'..='\u{28ff}').contains(&c)));
+
+        // The quiet zone is lit, except past the right edge of the last column
+        assert_eq!(lines[0], format!("{}\u{2847}", "\u{28ff}".repeat(14)));
+
+        let inverted = encode_to_braille(b"foo", &opts, true).unwrap();
+        assert_eq!(
+            inverted.lines().next().unwrap().chars().next(),
+            Some('\u{2800}')
+        );
+    }
+
+    #[test]
+    fn test_logo() {
+        let logo = image::RgbImage::from_pixel(40, 20, image::Rgb([255, 0, 0]));
+        let opts = RenderOptions {
+            logo: Some(logo.into()),
+            ..Default::default()
+        };
+
+        let image = build_binary_image(b"foo logo", &opts).unwrap();
+        let center = image.get_pixel(image.width() / 2, image.height() / 2);
+        assert_eq!(center.0, [255, 0, 0, 255]);
+
+        let codes = scan_image(&image.into()).unwrap();
+        assert_eq!(codes[0].content, b"foo logo");
+        assert_eq!(codes[0].ecc_level, 2);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let opts = RenderOptions {
+            gradient: Some(Gradient {
+                start: "#f00".parse().unwrap(),
+                end: "#00f".parse().unwrap(),
+                angle: 0.0,
+            }),
+            ..Default::default()
+        };
+
+        let image = build_binary_image(b"foo gradient", &opts).unwrap();
+        let codes = scan_image(&image.clone().into()).unwrap();
+        assert_eq!(codes[0].content, b"foo gradient");
+
+        // The top left and right finder patterns are dark
+        let (left, right) = (image.get_pixel(32, 32), image.get_pixel(199, 32));
+        assert!(left.0[0] > left.0[2]);
+        assert!(right.0[0] < right.0[2]);
+    }
+
+    #[test]
+    fn test_module_style() {
+        let dark_pixels = |style| {
+            let opts = RenderOptions {
+                style,
+                ..Default::default()
+            };
+            let image = build_binary_image(b"foo style", &opts).unwrap();
+
+            // The corner of the top left finder pattern stays square
+            assert_eq!(image.get_pixel(32, 32).0, [0, 0, 0, 255]);
+
+            let codes = scan_image(&image.clone().into()).unwrap();
+            assert_eq!(codes[0].content, b"foo style");
+
+            image.pixels().filter(|p| p.0 == [0, 0, 0, 255]).count()
+        };
+
+        let square = dark_pixels(ModuleStyle::Square);
+        let rounded = dark_pixels(ModuleStyle::Rounded);
+        let dots = dark_pixels(ModuleStyle::Dots);
+        assert!(square > rounded);
+        assert!(rounded > dots);
+    }
+
+    #[test]
+    fn test_pdf() {
+        let pdf = encode_to_pdf(b"foo pdf", &Default::default()).unwrap();
+        let pdf = String::from_utf8(pdf).unwrap();
+
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/MediaBox [0 0 232 232]"));
+        assert!(pdf.ends_with("%%EOF\n"));
+
+        let xref = pdf.find("xref\n").unwrap();
+        assert!(pdf.ends_with(&format!("startxref\n{xref}\n%%EOF\n")));
+    }
+
+    #[test]
+    fn test_eps() {
+        let opts = RenderOptions {
+            dark: "#f00".parse().unwrap(),
+            quiet_zone: 2,
+            ..Default::default()
+        };
+        let eps = encode_to_eps(b"foo eps", &opts).unwrap();
+
+        assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 200 200\n"));
+        assert!(eps.contains("1.000 1.000 1.000 setrgbcolor 0 0 200 200 rectfill\n"));
+        assert!(eps.contains("1.000 0.000 0.000 setrgbcolor\n"));
+        assert!(eps.ends_with("showpage\n%%EOF\n"));
+
+        // The top row of the finder patterns, from the top left after the quiet zone
+        assert!(eps.contains("\n16 176 56 8 rectfill\n"));
+    }
+
+    #[test]
+    fn test_scan_blank_image() {
+        let image = image::DynamicImage::new_rgb8(100, 100);
+        assert!(scan_image(&image).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_count_grids() {
+        let png = encode_to_png(b"foo grids", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(count_grids(&image, &Default::default()), 1);
+
+        let image = image::DynamicImage::new_rgb8(100, 100);
+        assert_eq!(count_grids(&image, &Default::default()), 0);
+    }
+
+    #[test]
+    fn test_scan_rotated() {
+        let png = encode_to_png(b"foo rotated", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap().rotate90();
+
+        let codes = scan_image(&image).unwrap();
+        assert_eq!(codes[0].content, b"foo rotated");
+
+        let opts = ScanOptions {
+            rotate: false,
+            ..Default::default()
+        };
+        let codes = scan_image_with(&image.rotate270(), &opts).unwrap();
+        assert_eq!(codes[0].content, b"foo rotated");
+    }
+
+    #[test]
+    fn test_scan_bounds() {
+        let png = encode_to_png(b"foo bounds", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+
+        let codes = scan_image(&image).unwrap();
+        let bounds = codes[0].bounds;
+
+        let opts = RenderOptions {
+            module_size: 1,
+            ..Default::default()
+        };
+        let png = encode_to_png(b"foo bounds", &opts).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+
+        let opts = ScanOptions {
+            min_size: Some(232),
+            ..Default::default()
+        };
+        let codes = scan_image_with(&image, &opts).unwrap();
+        assert_eq!(codes[0].bounds, bounds.map(|(x, y)| (x / 8, y / 8)));
+    }
+
+    #[test]
+    fn test_scan_threshold() {
+        let opts = RenderOptions {
+            dark: "#333".parse().unwrap(),
+            light: "#ccc".parse().unwrap(),
+            ..Default::default()
+        };
+        let png = encode_to_png(b"foo threshold", &opts).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+
+        for threshold in [Threshold::Fixed(0x80), Threshold::Adaptive] {
+            let opts = ScanOptions {
+                threshold: Some(threshold),
+                ..Default::default()
+            };
+            let codes = scan_image_with(&image, &opts).unwrap();
+            assert_eq!(codes[0].content, b"foo threshold");
+        }
+
+        let opts = ScanOptions {
+            threshold: Some(Threshold::Fixed(u8::MAX)),
+            ..Default::default()
+        };
+        assert!(scan_image_with(&image, &opts).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_min_size() {
+        let opts = RenderOptions {
+            module_size: 1,
+            ..Default::default()
+        };
+        let png = encode_to_png(b"foo small", &opts).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+
+        let opts = ScanOptions {
+            min_size: Some(200),
+            ..Default::default()
+        };
+        let codes = scan_image_with(&image, &opts).unwrap();
+        assert_eq!(codes[0].content, b"foo small");
+
+        // Too wide to upscale
+        let image = image::DynamicImage::new_luma8(1 << 20, 1);
+        let opts = ScanOptions {
+            min_size: Some(8192),
+            symbology: Some(Symbology::Qr),
+            ..Default::default()
+        };
+        assert!(scan_image_with(&image, &opts).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_max_dimension() {
+        let png = encode_to_png(b"foo large", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        let bounds = scan_image(&image).unwrap()[0].bounds;
+
+        let opts = ScanOptions {
+            max_dimension: Some(116),
+            ..Default::default()
+        };
+        let codes = scan_image_with(&image, &opts).unwrap();
+        assert_eq!(codes[0].content, b"foo large");
+
+        // The corners are mapped back to the full resolution, within a downscaled pixel
+        for ((x, y), (expected_x, expected_y)) in codes[0].bounds.iter().zip(bounds) {
+            assert!((x - expected_x).abs() <= 2 && (y - expected_y).abs() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_eci() {
+        use qrencode::bits::Bits;
+        use qrencode::types::Version;
+
+        let scan = |eci: Option<u32>, data: &[u8], kanji: bool| {
+            let mut bits = Bits::new(Version::Normal(2));
+            if let Some(eci) = eci {
+                bits.push_eci_designator(eci).unwrap();
+            }
+            if kanji {
+                bits.push_kanji_data(data).unwrap();
+            } else {
+                bits.push_byte_data(data).unwrap();
+            }
+            bits.push_terminator(EcLevel::L).unwrap();
+
+            let code = qrencode::QrCode::with_bits(bits, EcLevel::L).unwrap();
+            let image = code.render::<image::Luma<u8>>().build();
+            scan_image(&image.into()).unwrap().remove(0)
+        };
+
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("日本語");
+        let code = scan(Some(20), &sjis, false);
+        assert_eq!(code.content, sjis.as_ref());
+        assert_eq!(code.encoding, Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(code.text().unwrap(), "日本語");
+
+        let code = scan(None, &sjis, true);
+        assert_eq!(code.encoding, Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(code.text().unwrap(), "日本語");
+
+        let code = scan(Some(3), b"caf\xe9", false);
+        assert_eq!(code.encoding.unwrap().name(), "windows-1252");
+        assert_eq!(code.text().unwrap(), "café");
+
+        let code = scan(None, b"caf\xe9", false);
+        assert_eq!(code.encoding, None);
+        assert_eq!(code.text(), None);
+        assert_eq!(code.text_lossy(), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn test_scan_symbologies() {
+        use rxing::Writer;
+
+        let writer = rxing::MultiFormatWriter;
+        for (symbology, format) in [
+            (Symbology::Aztec, rxing::BarcodeFormat::AZTEC),
+            (Symbology::DataMatrix, rxing::BarcodeFormat::DATA_MATRIX),
+        ] {
+            let matrix = writer.encode("foo symbology", &format, 0, 0).unwrap();
+            let (width, height) = (matrix.getWidth(), matrix.getHeight());
+
+            // With a margin and bigger modules
+            let image =
+                image::GrayImage::from_fn((width + 8) * 4, (height + 8) * 4, |x, y| {
+                    let (x, y) = ((x / 4).wrapping_sub(4), (y / 4).wrapping_sub(4));
+                    let dark = x < width && y < height && matrix.get(x, y);
+                    image::Luma([if dark { 0 } else { 255 }])
+                });
+            let image = image.into();
+
+            let codes = scan_image(&image).unwrap();
+            assert_eq!(codes[0].content, b"foo symbology");
+            assert_eq!(codes[0].symbology, symbology);
+
+            // Not when looking for QR codes only
+            let opts = ScanOptions {
+                symbology: Some(Symbology::Qr),
+                ..Default::default()
+            };
+            assert!(scan_image_with(&image, &opts).unwrap().is_empty());
+
+            let opts = ScanOptions {
+                symbology: Some(symbology),
+                ..Default::default()
+            };
+            assert_eq!(
+                scan_image_with(&image, &opts).unwrap()[0].symbology,
+                symbology
+            );
+        }
+
+        let png = encode_to_png(b"foo", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(scan_image(&image).unwrap()[0].symbology, Symbology::Qr);
+
+        let opts = ScanOptions {
+            symbology: Some(Symbology::Aztec),
+            ..Default::default()
+        };
+        assert!(scan_image_with(&image, &opts).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_barcode() {
+        use rxing::Writer;
+
+        let matrix = rxing::MultiFormatWriter
+            .encode("5901234123457", &rxing::BarcodeFormat::EAN_13, 0, 0)
+            .unwrap();
+        let width = matrix.getWidth();
+        let image = image::GrayImage::from_fn((width + 20) * 2, 60, |x, y| {
+            let x = (x / 2).wrapping_sub(10);
+            let dark = (10..50).contains(&y) && x < width && matrix.get(x, 0);
+            image::Luma([if dark { 0 } else { 255 }])
+        });
+        let image = image.into();
+
+        // Only on demand
+        assert!(scan_image(&image).unwrap().is_empty());
+
+        let opts = ScanOptions {
+            symbology: Some(Symbology::Ean13),
+            ..Default::default()
+        };
+        let codes = scan_image_with(&image, &opts).unwrap();
+        assert_eq!(codes[0].content, b"5901234123457");
+        assert_eq!(codes[0].symbology, Symbology::Ean13);
+        assert!(codes[0].symbology.is_linear());
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("https://example.com"), ContentType::Url);
+        assert_eq!(classify("HTTP://EXAMPLE.COM"), ContentType::Url);
+        assert_eq!(classify("mailto:foo@example.com"), ContentType::Email);
+        assert_eq!(classify("tel:+123456789"), ContentType::Phone);
+        assert_eq!(classify("geo:37.78,-122.4"), ContentType::Geo);
+        assert_eq!(
+            classify("BEGIN:VCARD\nVERSION:3.0\nEND:VCARD"),
+            ContentType::VCard
+        );
+        assert_eq!(classify("WIFI:S:foo;;"), ContentType::Wifi);
+        assert_eq!(classify("httpfoo"), ContentType::Text);
+        assert_eq!(classify("ü"), ContentType::Text);
+    }
+
+    #[test]
+    fn test_parse_wifi() {
+        let wifi = parse_wifi(r#"WIFI:T:WPA;S:my\;net\:work;P:pa\\ss\"word;H:true;;"#);
+
+        assert_eq!(
+            wifi,
+            Some(WifiNetwork {
+                ssid: "my;net:work".into(),
+                auth: "WPA".into(),
+                password: Some(r#"pa\ss"word"#.into()),
+                hidden: true,
+            })
+        );
+
+        let wifi = parse_wifi("WIFI:S:open;;").unwrap();
+        assert_eq!(wifi.auth, "nopass");
+        assert_eq!(wifi.password, None);
+        assert!(!wifi.hidden);
+
+        assert_eq!(parse_wifi("WIFI:T:WPA;P:pass;;"), None);
+        assert_eq!(parse_wifi("https://example.com"), None);
+    }
+
+    #[test]
+    fn test_scan_error() {
+        let opts = RenderOptions {
+            module_size: 1,
+            ..Default::default()
+        };
+        let mut image = build_binary_image(b"foo", &opts).unwrap();
+
+        // Flip the data modules in the bottom right
+        for y in 4 + 9..4 + 21 {
+            for x in 4 + 9..4 + 21 {
+                let pixel = image.get_pixel_mut(x, y);
+                pixel.0[..3].iter_mut().for_each(|c| *c = u8::MAX - *c);
+            }
+        }
+        let opts = ScanOptions {
+            min_size: Some(200),
+            ..Default::default()
+        };
+        let result = scan_image_with(&image::DynamicImage::ImageRgba8(image), &opts);
+
+        match result {
+            Err(ScanError::DecodeFailed(err)) => assert!(!err.to_string().is_empty()),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert_eq!(ScanError::NoCodeFound.to_string(), "no QR code found");
+    }
+
+    #[test]
+    fn test_version_range() {
+        let opts = RenderOptions {
+            min_version: 5,
+            ..Default::default()
+        };
+        let png = encode_to_png(b"foo", &opts).unwrap();
+        let codes = scan_image(&image::load_from_memory(&png).unwrap()).unwrap();
+        assert_eq!(codes[0].version, 5);
+        assert_eq!(codes[0].content, b"foo");
+
+        let opts = RenderOptions {
+            max_version: 1,
+            ..Default::default()
+        };
+        let err = encode_to_svg(&[b'a'; 100], &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the content needs version 6, above the maximum version 1"
+        );
+
+        let opts = RenderOptions {
+            min_version: 3,
+            max_version: 2,
+            ..Default::default()
+        };
+        assert!(encode_to_ascii(b"foo", &opts).is_err());
+    }
+
+    #[test]
+    fn test_structured_append() {
+        let content: Vec<u8> = (0..240).map(|i| b'a' + (i % 26) as u8).collect();
+        let opts = RenderOptions {
+            max_version: 5,
+            module_size: 4,
+            ..Default::default()
+        };
+        let parts = split_content(&content, &opts).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[2].1.index, 2);
+        assert_eq!(parts[2].1.total, 3);
+
+        // Side by side, last first
+        let images: Vec<_> = parts
+            .iter()
+            .rev()
+            .map(|(chunk, part)| {
+                let opts = RenderOptions {
+                    structured_append: Some(*part),
+                    ..opts.clone()
+                };
+                build_binary_image(chunk, &opts).unwrap()
+            })
+            .collect();
+        let size = images[0].width();
+        let mut image = image::RgbaImage::new(size * 3, size);
+        for (i, part) in images.iter().enumerate() {
+            image::imageops::overlay(&mut image, part, (i as u32 * size) as i64, 0);
+        }
+        let image = image::DynamicImage::ImageRgba8(image);
+
+        let codes = scan_image(&image).unwrap();
+        assert_eq!(codes.len(), 3);
+        assert_eq!(codes[0].version, 5);
+        assert_eq!(codes[0].structured_append, Some(parts[2].1));
+        assert_eq!(codes[0].content, parts[2].0);
+
+        let joined = join_parts(codes.clone()).unwrap();
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0].content, content);
+        assert_eq!(joined[0].structured_append, None);
+
+        let err = join_parts(codes[..2].to_vec()).unwrap_err();
+        assert!(matches!(
+            err,
+            ScanError::IncompleteSequence { found: 2, total: 3 }
+        ));
+
+        let opts = RenderOptions {
+            max_version: 1,
+            ..Default::default()
+        };
+        assert!(split_content(&content, &opts).is_err());
+    }
+
+    #[test]
+    fn test_micro() {
+        let opts = RenderOptions {
+            micro: true,
+            module_size: 4,
+            ..Default::default()
+        };
+        let image = build_binary_image(b"A-1234", &opts).unwrap();
+        assert_eq!(image.width(), (15 + 2 * 4) * 4);
+
+        let codes = scan_image(&image::DynamicImage::ImageRgba8(image)).unwrap();
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].symbology, Symbology::MicroQr);
+        assert_eq!(codes[0].content, b"A-1234");
+
+        let opts = RenderOptions {
+            micro: true,
+            ..Default::default()
+        };
+        assert!(encode_to_svg(&[b'a'; 40], &opts).is_err());
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let opts = RenderOptions {
+            light: "#00000000".parse().unwrap(),
+            ..Default::default()
+        };
+
+        let image = build_binary_image(b"foo transparent", &opts).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(image.get_pixel(32, 32).0, [0, 0, 0, 255]);
+
+        let png = encode_to_png(b"foo transparent", &opts).unwrap();
+        let codes = scan_image(&image::load_from_memory(&png).unwrap()).unwrap();
+        assert_eq!(codes[0].content, b"foo transparent");
+
+        // On white instead of black
+        let jpeg = encode_to_jpeg(b"foo transparent", &opts).unwrap();
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert!(image.get_pixel(0, 0).0.iter().all(|&channel| channel > 240));
+        let codes = scan_image(&image.into()).unwrap();
+        assert_eq!(codes[0].content, b"foo transparent");
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let opts = RenderOptions {
+            module_size: 8,
+            ..Default::default()
+        };
+        let mut image = build_binary_image(b"foo metadata", &opts).unwrap();
+        let codes = scan_image(&image.clone().into()).unwrap();
+
+        // Wipe the data modules, leaving the patterns and the format information
+        let quiet_zone = 4 * 8;
+        for y in quiet_zone + 9 * 8..quiet_zone + 21 * 8 {
+            for x in quiet_zone + 9 * 8..quiet_zone + 21 * 8 {
+                image.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        let image = image::DynamicImage::ImageRgba8(image);
+        assert!(matches!(
+            scan_image(&image),
+            Err(ScanError::DecodeFailed(_))
+        ));
+
+        let metadata = read_metadata(&image, &Default::default());
+        assert_eq!(metadata.len(), 1);
+        assert!(metadata[0].content.is_empty());
+        assert_eq!(metadata[0].version, codes[0].version);
+        assert_eq!(metadata[0].ecc_level, codes[0].ecc_level);
+        assert_eq!(metadata[0].mask, codes[0].mask);
+        assert_eq!(metadata[0].bounds, codes[0].bounds);
+    }
+
+    #[test]
+    fn test_matrix() {
+        let opts = RenderOptions {
+            quiet_zone: 0,
+            module_size: 1,
+            ..Default::default()
+        };
+        let matrix = encode_to_matrix(b"foo matrix", &opts).unwrap();
+        assert_eq!(matrix.len(), 21);
+        assert!(matrix.iter().all(|row| row.len() == 21));
+
+        // The finder pattern of the top left corner
+        assert_eq!(
+            matrix[0][..8],
+            [true, true, true, true, true, true, true, false]
+        );
+        assert_eq!(
+            matrix[1][..8],
+            [true, false, false, false, false, false, true, false]
+        );
+
+        let image = build_binary_image(b"foo matrix", &opts).unwrap();
+        for (y, row) in matrix.iter().enumerate() {
+            for (x, dark) in row.iter().enumerate() {
+                assert_eq!(image.get_pixel(x as u32, y as u32).0[0] == 0, *dark);
+            }
+        }
+
+        let opts = RenderOptions {
+            module_size: 4,
+            ..Default::default()
+        };
+        let image = build_binary_image(b"foo matrix", &opts).unwrap();
+        let codes = scan_image(&image.into()).unwrap();
+        assert_eq!(codes[0].modules, matrix);
+    }
+
+    #[test]
+    fn test_scan_inverted() {
+        let opts = RenderOptions {
+            dark: "#fff".parse().unwrap(),
+            light: "#000".parse().unwrap(),
+            ..Default::default()
+        };
+        let image = build_binary_image(b"foo inverted", &opts).unwrap().into();
+        assert!(scan_image(&image).unwrap().is_empty());
+
+        let opts = ScanOptions {
+            invert: true,
+            ..Default::default()
+        };
+        let codes = scan_image_with(&image, &opts).unwrap();
+        assert_eq!(codes[0].content, b"foo inverted");
+    }
+
+    #[test]
+    fn test_profile_scan() {
+        let png = encode_to_png(b"foo profile", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+
+        let profile = profile_scan(&image, &Default::default());
+        assert_eq!(profile.grids, 1);
+        assert_eq!(profile.decoded, 1);
+        assert!(profile.detect > std::time::Duration::ZERO);
+
+        let blank = image::DynamicImage::new_luma8(100, 100);
+        let profile = profile_scan(&blank, &Default::default());
+        assert_eq!(profile.grids, 0);
+        assert_eq!(profile.decoded, 0);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = encode_to_png(b"foo capacity", &Default::default()).unwrap();
+        let codes = scan_image(&image::load_from_memory(&png).unwrap()).unwrap();
+        assert_eq!(codes[0].capacity(), Some(14));
+
+        let code = |version, ecc_level| DecodedCode {
+            version,
+            ecc_level,
+            ..codes[0].clone()
+        };
+        assert_eq!(code(1, 1).capacity(), Some(17));
+        assert_eq!(code(1, 2).capacity(), Some(7));
+        assert_eq!(code(10, 3).capacity(), Some(151));
+        assert_eq!(code(40, 1).capacity(), Some(2953));
+    }
+
+    #[test]
+    fn test_colors() {
+        for (dark, light, expected_dark, expected_light) in [
+            ("red", "#888", [255, 0, 0, 255], [136, 136, 136, 255]),
+            (
+                "rebeccapurple",
+                "#ffffff80",
+                [102, 51, 153, 255],
+                [255, 255, 255, 128],
+            ),
+        ] {
+            let opts = RenderOptions {
+                dark: dark.parse().unwrap(),
+                light: light.parse().unwrap(),
+                module_size: 1,
+                ..Default::default()
+            };
+            let image = build_binary_image(b"foo colors", &opts).unwrap();
+            assert_eq!(image.get_pixel(4, 4).0, expected_dark);
+            assert_eq!(image.get_pixel(0, 0).0, expected_light);
+        }
+    }
+
+    #[test]
+    fn test_mask() {
+        for mask in 0..8 {
+            let opts = RenderOptions {
+                mask: Some(mask),
+                min_version: 2,
+                ..Default::default()
+            };
+            let image = build_binary_image(b"foo mask", &opts).unwrap();
+            let codes = scan_image(&image.into()).unwrap();
+            assert_eq!(codes[0].content, b"foo mask");
+            assert_eq!(codes[0].mask, mask as u16);
+            assert_eq!(codes[0].version, 2);
+        }
+
+        let opts = RenderOptions {
+            mask: Some(4),
+            micro: true,
+            ..Default::default()
+        };
+        assert!(build_binary_image(b"foo", &opts).is_err());
+    }
+
+    #[test]
+    fn test_modules_range() {
+        let opts = RenderOptions {
+            min_version: 2,
+            ..Default::default()
+        };
+        let image: image::DynamicImage =
+            build_binary_image(b"foo modules", &opts).unwrap().into();
+
+        let scan = |min_modules, max_modules| {
+            let opts = ScanOptions {
+                min_modules,
+                max_modules,
+                ..Default::default()
+            };
+            scan_image_with(&image, &opts).unwrap().len()
+        };
+        assert_eq!(scan(21, 177), 1);
+        assert_eq!(scan(25, 25), 1);
+        assert_eq!(scan(29, 177), 0);
+        assert_eq!(scan(21, 21), 0);
+    }
+
+    #[test]
+    fn test_corrections() {
+        let opts = RenderOptions {
+            ec_level: EcLevel::L,
+            ..Default::default()
+        };
+        let image = build_binary_image(b"foo corrections", &opts).unwrap();
+        let codes = scan_image(&image.clone().into()).unwrap();
+        assert_eq!(
+            codes[0].corrections,
+            Some(Corrections {
+                errors: 0,
+                max_errors: 3,
+                block_errors: 0,
+                block_max_errors: 3,
+            })
+        );
+
+        // Flip the modules of the first codewords, at the bottom right corner
+        let damage = |rows: u32| {
+            let mut damaged = image.clone();
+            for (x, y) in (0..2 * 8).flat_map(|x| (0..rows * 8).map(move |y| (x, y))) {
+                let pixel = damaged.get_pixel_mut(25 * 8 - 1 - x, 25 * 8 - 1 - y);
+                pixel.0[..3].iter_mut().for_each(|c| *c = 255 - *c);
+            }
+            let codes = scan_image(&damaged.into()).unwrap();
+            assert_eq!(codes[0].content, b"foo corrections");
+            codes[0].corrections.unwrap().errors
+        };
+        assert_eq!(damage(2), 1);
+        assert_eq!(damage(5), 2);
+    }
+
+    #[test]
+    fn test_corrections_blocks() {
+        // Version 5-Q has 4 blocks of 9 correctable errors, interleaved codeword by
+        // codeword
+        let opts = RenderOptions {
+            ec_level: EcLevel::Q,
+            min_version: 5,
+            ..Default::default()
+        };
+        let mut image = build_binary_image(b"foo corrections blocks", &opts).unwrap();
+
+        // Flip the codewords 0, 4, 8, 12 and 16, all in the first block, each 2
+        // modules wide and 4 high in the columns read up and down from the bottom
+        // right corner
+        let codewords = [(35, 33), (35, 17), (33, 13), (33, 29), (31, 20)];
+        for (x, y) in codewords {
+            for (dx, dy) in (0..2).flat_map(|dx| (0..4).map(move |dy| (dx, dy))) {
+                for (px, py) in (0..8).flat_map(|px| (0..8).map(move |py| (px, py))) {
+                    let pixel = image
+                        .get_pixel_mut((x + dx + 4) * 8 + px, (y + dy + 4) * 8 + py);
+                    pixel.0[..3].iter_mut().for_each(|c| *c = 255 - *c);
+                }
+            }
+        }
+
+        let codes = scan_image(&image.into()).unwrap();
+        assert_eq!(codes[0].content, b"foo corrections blocks");
+        let corrections = codes[0].corrections.unwrap();
+        assert_eq!(
+            corrections,
+            Corrections {
+                errors: 5,
+                max_errors: 36,
+                block_errors: 5,
+                block_max_errors: 9,
+            }
+        );
+        assert!(corrections.is_damaged());
+    }
+
+    #[test]
+    fn test_url_decode() {
+        assert_eq!(
+            url_decode("https://example.com/a%20b?q=%C3%A9+c").as_deref(),
+            Some("https://example.com/a b?q=é+c")
+        );
+        assert_eq!(
+            url_decode("mailto:foo%40example.com").as_deref(),
+            Some("mailto:foo@example.com")
+        );
+        assert_eq!(url_decode("http://%FF").as_deref(), Some("http://\u{fffd}"));
+        assert_eq!(url_decode("100%20 sure"), None);
+        assert_eq!(url_decode("WIFI:S:a%20b;;"), None);
+    }
+
+    #[test]
+    fn test_color_space() {
+        for (color_space, expected_light) in [
+            (ColorSpace::Srgb, [136, 136, 136, 255]),
+            (ColorSpace::Linear, [63, 63, 63, 255]),
+        ] {
+            let opts = RenderOptions {
+                dark: "red".parse().unwrap(),
+                light: "#888".parse().unwrap(),
+                color_space,
+                module_size: 1,
+                ..Default::default()
+            };
+            let image = build_binary_image(b"foo color space", &opts).unwrap();
+            assert_eq!(image.get_pixel(4, 4).0, [255, 0, 0, 255]);
+            assert_eq!(image.get_pixel(0, 0).0, expected_light);
+
+            // Vector images are always in sRGB
+            let svg = encode_to_svg(b"foo color space", &opts).unwrap();
+            assert!(svg.contains("#888888"));
+        }
+    }
+
+    #[test]
+    fn test_scan_grids() {
+        let opts = RenderOptions::default();
+        let good = build_binary_image(b"foo grids", &opts).unwrap();
+        let mut damaged = build_binary_image(b"bar grids", &opts).unwrap();
+
+        // Wipe the data modules, leaving the patterns and the format information
+        let quiet_zone = 4 * 8;
+        for y in quiet_zone + 9 * 8..quiet_zone + 21 * 8 {
+            for x in quiet_zone + 9 * 8..quiet_zone + 21 * 8 {
+                damaged.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+
+        // Side by side
+        let mut image = image::RgbaImage::new(good.width() * 2, good.height());
+        image::imageops::replace(&mut image, &good, 0, 0);
+        image::imageops::replace(&mut image, &damaged, good.width() as i64, 0);
+        let image = image::DynamicImage::ImageRgba8(image);
+
+        let codes = scan_image(&image).unwrap();
+        assert_eq!(codes.len(), 1);
+
+        let results = scan_grids(&image, &Default::default());
+        assert_eq!(results.len(), 2);
+        let (decoded, failed): (Vec<_>, Vec<_>) =
+            results.into_iter().partition(Result::is_ok);
+        assert_eq!(decoded[0].as_ref().unwrap().content, b"foo grids");
+        let failed = failed[0].as_ref().unwrap_err();
+        assert!(matches!(failed.error, ScanError::DecodeFailed(_)));
+        assert!(failed.bounds.iter().all(|&(x, _)| x >= good.width() as i32));
+
+        let (codes, failed) = scan_image_failed(&image, &Default::default()).unwrap();
+        assert_eq!(codes[0].content, b"foo grids");
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0]
+            .bounds
+            .iter()
+            .all(|&(x, _)| x >= good.width() as i32));
+    }
+}
@@ -1,487 +1,6957 @@
+mod archive;
+mod exif;
+
 use anyhow::Result;
+use clap::builder::FalseyValueParser;
+use clap::CommandFactory;
 use clap::Parser;
+use clap::ValueEnum;
 use csscolorparser::Color;
-use image::codecs::jpeg::JpegEncoder;
-use image::codecs::png::PngEncoder;
+use image::codecs::gif::GifDecoder;
 use image::io::Reader as ImageReader;
-use image::ColorType;
+use image::AnimationDecoder;
 use image::DynamicImage;
-use image::EncodableLayout;
-use image::ImageBuffer;
-use image::ImageEncoder;
-use image::Rgba;
+use image::ImageFormat;
+use image::RgbImage;
 use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::ApiBackend;
+use nokhwa::utils::CameraFormat;
 use nokhwa::utils::CameraIndex;
+use nokhwa::utils::FrameFormat;
 use nokhwa::utils::RequestedFormat;
 use nokhwa::utils::RequestedFormatType;
 use nokhwa::Camera;
-use qrencode::render::svg;
-use qrencode::render::unicode::Dense1x2;
-use qrencode::render::unicode::Dense1x2::Dark;
-use qrencode::render::unicode::Dense1x2::Light;
-use qrencode::QrCode;
+use pdfium_render::prelude::PdfRenderConfig;
+use pdfium_render::prelude::Pdfium;
+use qrscan::ColorSpace;
+use qrscan::DecodedCode;
+use qrscan::EcLevel;
+use qrscan::FailedGrid;
+use qrscan::Gradient;
+use qrscan::ModuleStyle;
+use qrscan::RenderOptions;
+use qrscan::ScanError;
+use qrscan::ScanOptions;
+use qrscan::StructuredAppend;
+use qrscan::Symbology;
+use qrscan::Threshold;
+use qrscan::WifiNetwork;
+use qrscan::MAX_IMAGE_SIZE;
+use qrscan::MAX_MODULE_SIZE;
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::ParallelIterator;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::BufReader;
 use std::io::Cursor;
+use std::io::IsTerminal;
 use std::io::Read;
 use std::io::Write;
+use std::path::Component;
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::time::Duration;
+use std::time::Instant;
 
 static PROGRESS: &[&str] = &["   ", ".  ", ".. ", "..."];
 
+/// The URLs opened so far, so that watch mode opens each of them only once.
+static OPENED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// The names and contents of the exported files to write into the --bundle archive.
+static BUNDLE: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+
+/// Whether the crop region was clamped already, to warn only once and not on every
+/// camera frame.
+static CROP_CLAMPED: AtomicBool = AtomicBool::new(false);
+
+/// Whether sending a notification failed already, to warn only once.
+static NOTIFY_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// The image of --logo, once loaded.
+static LOGO: OnceLock<DynamicImage> = OnceLock::new();
+
+/// The maximum number of characters of the content to show in a notification.
+const NOTIFY_MAX_CHARS: usize = 200;
+
+/// The lowest contrast ratio between the gradient colors and the background.
+const MIN_CONTRAST: f64 = 3.0;
+
+/// The first bytes of every PNG image.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The largest image to read from a --stream, so that a stream of something else
+/// fails instead of allocating gigabytes for the length it seems to be prefixed with.
+const MAX_FRAME_SIZE: u64 = 64 << 20;
+
+/// How much of a JPEG file to read for its EXIF orientation. The APP1 segment holding
+/// the EXIF data takes at most 64 KiB, with room for the APP0 (JFIF) segment that can
+/// come before it.
+const JPEG_HEAD_SIZE: u64 = 1 << 17;
+
+/// The widest quiet zone, in modules, far beyond the standard 4 but keeping the
+/// largest codes within a sane image size.
+const MAX_QUIET_ZONE: u32 = 64;
+
+/// The lowest JPEG quality that reliably keeps the modules sharp enough to scan.
+const MIN_JPEG_QUALITY: u8 = 50;
+
+/// The widest logo, in percent of the QR code's width, that is unlikely to make it
+/// unreadable.
+const MAX_SAFE_LOGO_SIZE: u32 = 30;
+
+/// How long to wait before retrying to open the camera the first time. The wait
+/// doubles on each retry.
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// How long to wait before trying to open the camera again with --wait-for-device,
+/// after the retries failed.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long each camera gets to show a non-blank frame with `--device auto`, long
+/// enough for the exposure of real webcams to settle.
+const PROBE_WINDOW: Duration = Duration::from_secs(2);
+
+/// The standard deviation of the brightness below which a frame counts as blank, like
+/// the plain feed of an idle virtual camera.
+const BLANK_DEVIATION: f64 = 4.0;
+
+/// How long a QR code stays out of view before it gets printed again in watch mode.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// The width in pixels to render the PDF pages at, enough for the small codes on
+/// printed documents.
+const PDF_RENDER_WIDTH: i32 = 2000;
+
+/// The brands of the HEIF images, like the HEIC photos of iPhones, that follow "ftyp"
+/// at the start of the file.
+static HEIF_BRANDS: &[&[u8]] = &[
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"hevm", b"hevs", b"mif1",
+    b"msf1",
+];
+
+/// The extensions of the video files to scan with ffmpeg.
+static VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mkv", "webm", "mov", "avi"];
+
+static FRAME_FORMATS: &[FrameFormat] = &[
+    FrameFormat::MJPEG,
+    FrameFormat::YUYV,
+    FrameFormat::NV12,
+    FrameFormat::RAWRGB,
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CameraFrameFormat {
+    Mjpeg,
+    Yuyv,
+    Nv12,
+    Rawrgb,
+}
+
+impl From<CameraFrameFormat> for FrameFormat {
+    fn from(format: CameraFrameFormat) -> Self {
+        match format {
+            CameraFrameFormat::Mjpeg => Self::MJPEG,
+            CameraFrameFormat::Yuyv => Self::YUYV,
+            CameraFrameFormat::Nv12 => Self::NV12,
+            CameraFrameFormat::Rawrgb => Self::RAWRGB,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ErrorCorrectionLevel {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl From<ErrorCorrectionLevel> for EcLevel {
+    fn from(level: ErrorCorrectionLevel) -> Self {
+        match level {
+            ErrorCorrectionLevel::L => Self::L,
+            ErrorCorrectionLevel::M => Self::M,
+            ErrorCorrectionLevel::Q => Self::Q,
+            ErrorCorrectionLevel::H => Self::H,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InputFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+    Bmp,
+    Tiff,
+    Ico,
+    Pnm,
+    Tga,
+    Qoi,
+    Hdr,
+    Farbfeld,
+}
+
+impl From<InputFormat> for ImageFormat {
+    fn from(format: InputFormat) -> Self {
+        match format {
+            InputFormat::Png => Self::Png,
+            InputFormat::Jpeg => Self::Jpeg,
+            InputFormat::Webp => Self::WebP,
+            InputFormat::Gif => Self::Gif,
+            InputFormat::Bmp => Self::Bmp,
+            InputFormat::Tiff => Self::Tiff,
+            InputFormat::Ico => Self::Ico,
+            InputFormat::Pnm => Self::Pnm,
+            InputFormat::Tga => Self::Tga,
+            InputFormat::Qoi => Self::Qoi,
+            InputFormat::Hdr => Self::Hdr,
+            InputFormat::Farbfeld => Self::Farbfeld,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Style {
+    Square,
+    Dots,
+    Rounded,
+}
+
+impl From<Style> for ModuleStyle {
+    fn from(style: Style) -> Self {
+        match style {
+            Style::Square => Self::Square,
+            Style::Dots => Self::Dots,
+            Style::Rounded => Self::Rounded,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorSpaceArg {
+    Srgb,
+    Linear,
+}
+
+impl From<ColorSpaceArg> for ColorSpace {
+    fn from(color_space: ColorSpaceArg) -> Self {
+        match color_space {
+            ColorSpaceArg::Srgb => Self::Srgb,
+            ColorSpaceArg::Linear => Self::Linear,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SymbologyArg {
+    Qr,
+    Microqr,
+    Aztec,
+    Datamatrix,
+    Pdf417,
+    Ean13,
+    Ean8,
+    Upca,
+    Upce,
+    Code128,
+    Code39,
+    Code93,
+    Codabar,
+    Itf,
+}
+
+impl From<SymbologyArg> for Symbology {
+    fn from(symbology: SymbologyArg) -> Self {
+        match symbology {
+            SymbologyArg::Qr => Self::Qr,
+            SymbologyArg::Microqr => Self::MicroQr,
+            SymbologyArg::Aztec => Self::Aztec,
+            SymbologyArg::Datamatrix => Self::DataMatrix,
+            SymbologyArg::Pdf417 => Self::Pdf417,
+            SymbologyArg::Ean13 => Self::Ean13,
+            SymbologyArg::Ean8 => Self::Ean8,
+            SymbologyArg::Upca => Self::UpcA,
+            SymbologyArg::Upce => Self::UpcE,
+            SymbologyArg::Code128 => Self::Code128,
+            SymbologyArg::Code39 => Self::Code39,
+            SymbologyArg::Code93 => Self::Code93,
+            SymbologyArg::Codabar => Self::Codabar,
+            SymbologyArg::Itf => Self::Itf,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum QrStyle {
+    /// Unicode blocks on a capable terminal, ASCII otherwise
+    Auto,
+    /// Unicode half blocks
+    Unicode,
+    /// ASCII characters
+    Ascii,
+    /// Braille patterns, for bigger codes
+    Braille,
+}
+
+/// A region of interest, in pixels.
+#[derive(Clone, Copy, Debug)]
+struct Crop {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|err| err.to_string())?;
+
+        match values[..] {
+            [x, y, width, height] => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err("expected X,Y,W,H".into()),
+        }
+    }
+}
+
+/// A preview dimension, in terminal cells or as a percentage of the terminal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PreviewSize {
+    Cells(u32),
+    Percent(u32),
+}
+
+impl PreviewSize {
+    /// The number of cells, out of the terminal's.
+    fn resolve(self, terminal: u16) -> u32 {
+        match self {
+            Self::Cells(cells) => cells,
+            Self::Percent(percent) => (terminal as u32 * percent / 100).max(1),
+        }
+    }
+}
+
+impl FromStr for PreviewSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse() {
+                Ok(percent @ 1..=100) => Ok(Self::Percent(percent)),
+                _ => Err("expected a percentage from 1% to 100%".into()),
+            },
+            None => s
+                .trim()
+                .parse()
+                .map(Self::Cells)
+                .map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Parse a gradient as COLOR1,COLOR2,ANGLE.
+fn parse_gradient(s: &str) -> Result<Gradient, String> {
+    let parts: Vec<_> = s.split(',').map(str::trim).collect();
+    let [start, end, angle] = parts[..] else {
+        return Err("expected COLOR1,COLOR2,ANGLE".into());
+    };
+
+    Ok(Gradient {
+        start: start.parse().map_err(|err| format!("{start}: {err}"))?,
+        end: end.parse().map_err(|err| format!("{end}: {err}"))?,
+        angle: angle.parse().map_err(|err| format!("{angle}: {err}"))?,
+    })
+}
+
 #[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_override_self = true)]
 struct Args {
-    /// Path to the image to scan. If not specified, the system camera will be used
+    /// Paths to the images or PDF documents to scan. If not specified, the system
+    /// camera will be used
     ///
     /// Examples:
     ///
     ///   qrscan /path/to/input.png
     ///
+    ///   qrscan /path/to/input1.png /path/to/input2.png
+    ///
+    ///   qrscan https://example.com/input.png
+    ///
     ///   cat /path/to/input.png | qrscan -
     #[clap(value_parser)]
-    image: Option<PathBuf>,
+    image: Vec<PathBuf>,
+
+    /// Generate a QR code from the given text instead of scanning. Use "-" to read
+    /// the text from stdin
+    ///
+    /// Examples:
+    ///
+    ///   qrscan --encode "hello world" --png /path/to/output.png
+    ///
+    ///   echo -n "hello world" | qrscan --encode -
+    #[clap(long, env = "QRSCAN_ENCODE", short, conflicts_with = "image")]
+    encode: Option<String>,
+
+    /// Camera device to use. Can be an index, a device path (e.g. /dev/video2), the
+    /// camera name, or "auto" to use the first camera that gives a non-blank frame,
+    /// skipping idle virtual cameras
+    #[clap(long, env = "QRSCAN_DEVICE", short, default_value = "0")]
+    device: String,
+
+    /// Number of times to retry opening the camera, waiting longer each time, for
+    /// devices that are slow to get ready after being plugged in
+    #[clap(long, env = "QRSCAN_OPEN_RETRIES", default_value = "2")]
+    open_retries: u32,
+
+    /// Wait for the camera to be plugged in instead of failing, for up to the given
+    /// number of seconds if any (exits with 4)
+    #[clap(long, env = "QRSCAN_WAIT_FOR_DEVICE", value_name = "SECONDS", num_args = 0..=1)]
+    wait_for_device: Option<Option<u64>>,
+
+    /// Number of camera frames to skip before scanning, while the exposure settles
+    #[clap(long, env = "QRSCAN_WARMUP", default_value = "5")]
+    warmup: u32,
+
+    /// Number of buffered camera frames to skip before each scan, so that the scan
+    /// sees the current view rather than a stale one (each takes a frame's time)
+    #[clap(long, env = "QRSCAN_DROP_FRAMES", default_value = "0")]
+    drop_frames: u32,
+
+    /// List the available cameras with their supported formats and exit
+    #[clap(long, env = "QRSCAN_LIST_CAMERAS", value_parser = FalseyValueParser::new())]
+    list_cameras: bool,
+
+    /// Camera resolution width (works with --camera-height)
+    #[clap(long, env = "QRSCAN_CAMERA_WIDTH", requires = "camera_height")]
+    camera_width: Option<u32>,
+
+    /// Camera resolution height (works with --camera-width)
+    #[clap(long, env = "QRSCAN_CAMERA_HEIGHT", requires = "camera_width")]
+    camera_height: Option<u32>,
+
+    /// Camera frame rate
+    #[clap(long, env = "QRSCAN_CAMERA_FPS")]
+    camera_fps: Option<u32>,
+
+    /// Camera frame format. If not specified, MJPEG is tried first, then the others
+    #[clap(long, env = "QRSCAN_FRAME_FORMAT", value_enum)]
+    frame_format: Option<CameraFrameFormat>,
+
+    /// Keep scanning via camera and print each new QR code (press Ctrl-C to stop)
+    #[clap(long, env = "QRSCAN_WATCH", short, value_parser = FalseyValueParser::new())]
+    watch: bool,
+
+    /// Remember each decoded content in the given file, and skip the ones already in
+    /// it, also across runs (works with --watch)
+    #[clap(long, env = "QRSCAN_DEDUP_FILE", requires = "watch")]
+    dedup_file: Option<PathBuf>,
+
+    /// Clear the history in the --dedup-file first
+    #[clap(long, env = "QRSCAN_RESET", requires = "dedup_file", value_parser = FalseyValueParser::new())]
+    reset: bool,
+
+    /// Save the camera frame the codes were decoded from as PNG to the given path.
+    /// "{timestamp}" gets replaced with the capture time, to keep each with --watch
+    #[clap(long, env = "QRSCAN_SAVE_FRAME")]
+    save_frame: Option<PathBuf>,
+
+    /// Stop scanning via camera after the given number of seconds (exits with 4)
+    #[clap(long, env = "QRSCAN_TIMEOUT")]
+    timeout: Option<u64>,
+
+    /// Stop waiting for stdin to close after the given number of seconds (exits with 4)
+    #[clap(long, env = "QRSCAN_STDIN_TIMEOUT")]
+    stdin_timeout: Option<u64>,
+
+    /// Keep reading images from stdin, printing the QR codes of each as it arrives,
+    /// until stdin is closed. The images are PNG images one after another, or of any
+    /// format each prefixed with its length in bytes as a 32-bit big-endian integer.
+    /// Exits with the error code of the first image that failed
+    #[clap(long, env = "QRSCAN_STREAM", conflicts_with_all = ["image", "encode"], value_parser = FalseyValueParser::new())]
+    stream: bool,
 
     /// Preview the camera on the terminal (if compatible)
-    #[clap(long, short)]
+    #[clap(long, env = "QRSCAN_PREVIEW", short, value_parser = FalseyValueParser::new())]
     preview: bool,
 
+    /// Print plain text instead of colors and blocks on the terminal, like when the
+    /// output is not a terminal or NO_COLOR is set
+    #[clap(long, env = "QRSCAN_NO_COLOR", value_parser = FalseyValueParser::new())]
+    no_color: bool,
+
     /// Preview display's x coordinate (works with --preview)
-    #[clap(long, default_value = "0")]
+    #[clap(long, env = "QRSCAN_PREVIEW_X", default_value = "0")]
     preview_x: u16,
 
     /// Preview diaplay's y coordinate (works with --preview)
-    #[clap(long, default_value = "0")]
+    #[clap(long, env = "QRSCAN_PREVIEW_Y", default_value = "0")]
     preview_y: i16,
 
-    /// Preview width (works with --preview)
-    #[clap(long)]
-    preview_w: Option<u32>,
+    /// Preview width in cells, or as a percentage of the terminal like 80% (works
+    /// with --preview)
+    #[clap(long, env = "QRSCAN_PREVIEW_W")]
+    preview_w: Option<PreviewSize>,
+
+    /// Preview height in cells, or as a percentage of the terminal like 80% (works
+    /// with --preview)
+    #[clap(long, env = "QRSCAN_PREVIEW_H")]
+    preview_h: Option<PreviewSize>,
+
+    /// Do not print the scanning progress to stderr (errors are still printed)
+    #[clap(long, env = "QRSCAN_QUIET", short, value_parser = FalseyValueParser::new())]
+    quiet: bool,
+
+    /// Print diagnostics to stderr, like the image size and how long scanning took.
+    /// Repeat (-vv) to also print the preprocessing steps and the number of QR
+    /// codes detected before decoding
+    #[clap(long, env = "QRSCAN_VERBOSE", short, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
 
-    /// Preview height (works with --preview)
-    #[clap(long)]
-    preview_h: Option<u32>,
+    /// Scan the image files in the given directories recursively
+    #[clap(long, env = "QRSCAN_RECURSIVE", short, value_parser = FalseyValueParser::new())]
+    recursive: bool,
+
+    /// Number of files to scan in parallel, printing the results in order. Defaults
+    /// to the number of CPUs
+    #[clap(long, env = "QRSCAN_JOBS", short, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
+    /// Do not retry on the rotated image when no QR code could be read
+    #[clap(long, env = "QRSCAN_NO_ROTATE", value_parser = FalseyValueParser::new())]
+    no_rotate: bool,
+
+    /// Only scan the given region of the image, as X,Y,W,H in pixels
+    #[clap(long, env = "QRSCAN_CROP")]
+    crop: Option<Crop>,
+
+    /// Upscale images whose smaller side is below the given number of pixels before
+    /// scanning (for tiny thumbnails, at most 8192)
+    #[clap(long, env = "QRSCAN_MIN_SIZE", value_parser = clap::value_parser!(u32).range(1..=MAX_IMAGE_SIZE as i64))]
+    min_size: Option<u32>,
+
+    /// Downscale images whose larger side is above the given number of pixels before
+    /// scanning, for speed (at least 21, the modules of the smallest QR code)
+    #[clap(long, env = "QRSCAN_MAX_DIMENSION", value_parser = clap::value_parser!(u32).range(21..))]
+    max_dimension: Option<u32>,
+
+    /// Discard the detected QR codes with fewer modules per side, as spurious
+    /// detections in noisy photos (from 21 to 177)
+    #[clap(long, env = "QRSCAN_MIN_MODULES", default_value = "21", value_parser = clap::value_parser!(u32).range(21..=177))]
+    min_modules: u32,
+
+    /// Discard the detected QR codes with more modules per side, as spurious
+    /// detections in noisy photos (from 21 to 177)
+    #[clap(long, env = "QRSCAN_MAX_MODULES", default_value = "177", value_parser = clap::value_parser!(u32).range(21..=177))]
+    max_modules: u32,
+
+    /// Decode the input images in the given format instead of guessing it from their
+    /// content, for files and stdin
+    #[clap(long, env = "QRSCAN_INPUT_FORMAT", value_enum, ignore_case = true)]
+    input_format: Option<InputFormat>,
+
+    /// Only look for the given kind of code. By default, Aztec, Data Matrix and
+    /// PDF417 codes are looked for when there is no QR code, and 1D barcodes (EAN,
+    /// UPC, Code 128...) only when given here
+    #[clap(long, env = "QRSCAN_SYMBOLOGY", value_enum, ignore_case = true)]
+    symbology: Option<SymbologyArg>,
+
+    /// Binarize the image before scanning: pixels brighter than the threshold (0-255)
+    /// become white, the others black
+    #[clap(long, env = "QRSCAN_THRESHOLD", conflicts_with = "adaptive_threshold")]
+    threshold: Option<u8>,
+
+    /// Binarize the image before scanning, comparing each pixel with the mean of its
+    /// neighborhood (for gradients and shadows)
+    #[clap(long, env = "QRSCAN_ADAPTIVE_THRESHOLD", value_parser = FalseyValueParser::new())]
+    adaptive_threshold: bool,
+
+    /// Invert the image before scanning, for light QR codes on a dark background
+    #[clap(long, env = "QRSCAN_INVERT_INPUT", value_parser = FalseyValueParser::new())]
+    invert_input: bool,
 
     /// Print metadata
-    #[clap(long, short)]
+    #[clap(long, env = "QRSCAN_METADATA", short, value_parser = FalseyValueParser::new())]
     metadata: bool,
 
+    /// Print only the metadata, even of the QR codes whose content fails to decode
+    #[clap(long, env = "QRSCAN_METADATA_ONLY", value_parser = FalseyValueParser::new())]
+    metadata_only: bool,
+
+    /// Print the number of QR codes found instead of their content (exits with 5
+    /// if there are none)
+    #[clap(long, env = "QRSCAN_COUNT", value_parser = FalseyValueParser::new())]
+    count: bool,
+
+    /// Print how long each stage of scanning the images takes instead of their
+    /// content, without retrying on the rotated image
+    #[clap(long, env = "QRSCAN_PROFILE", requires = "image", value_parser = FalseyValueParser::new())]
+    profile: bool,
+
+    /// Fail with exit code 7 unless the given number of QR codes is found (works
+    /// with --count)
+    #[clap(long, env = "QRSCAN_EXPECT", requires = "count")]
+    expect: Option<usize>,
+
+    /// Scan all the QR codes in the image instead of just the first one, including
+    /// the distinct codes in all the frames of animated GIF images (only the first
+    /// code gets exported)
+    #[clap(long, env = "QRSCAN_ALL", short, value_parser = FalseyValueParser::new())]
+    all: bool,
+
+    /// Scan only the given page of PDF documents, or frame of GIF images and videos,
+    /// counting from 1, instead of all of them
+    #[clap(long, env = "QRSCAN_PAGE", visible_alias = "frame", value_parser = clap::value_parser!(u32).range(1..))]
+    page: Option<u32>,
+
     /// Print the QR code
-    #[clap(long)]
+    #[clap(long, env = "QRSCAN_QR", value_parser = FalseyValueParser::new())]
     qr: bool,
 
+    /// How to print the QR code on the terminal
+    #[clap(long, env = "QRSCAN_QR_STYLE", value_enum, default_value = "auto")]
+    qr_style: QrStyle,
+
+    /// Print the modules of the QR code as its width and height, then rows of 0 and
+    /// 1 for the dark ones (a "matrix" object with --json)
+    #[clap(long, env = "QRSCAN_MATRIX", value_parser = FalseyValueParser::new())]
+    matrix: bool,
+
+    /// Print the result as JSON (an array of objects with --all), or the cameras with
+    /// --list-cameras
+    #[clap(long, env = "QRSCAN_JSON", value_parser = FalseyValueParser::new())]
+    json: bool,
+
+    /// Also list the detected QR codes that failed to decode, with "decoded": false
+    /// and the error instead of the content (works with --json and --all)
+    #[clap(long, env = "QRSCAN_INCLUDE_FAILED", requires = "json", requires = "all", value_parser = FalseyValueParser::new())]
+    include_failed: bool,
+
+    /// Prefix each result with the ISO-8601 time it was scanned at (a "timestamp"
+    /// field with --json)
+    #[clap(long, env = "QRSCAN_TIMESTAMPS", value_parser = FalseyValueParser::new())]
+    timestamps: bool,
+
+    /// Use UTC instead of the local time zone (works with --timestamps)
+    #[clap(long, env = "QRSCAN_UTC", requires = "timestamps", value_parser = FalseyValueParser::new())]
+    utc: bool,
+
+    /// Join the QR codes in the image that a content was split across with
+    /// structured append into one (fails if some are missing)
+    #[clap(long, env = "QRSCAN_JOIN", value_parser = FalseyValueParser::new())]
+    join: bool,
+
     /// Do not print the content
-    #[clap(long, short)]
+    #[clap(long, env = "QRSCAN_NO_CONTENT", short, value_parser = FalseyValueParser::new())]
     no_content: bool,
 
-    /// Interval between scans in milisecond
-    #[clap(long, short, default_value = "200")]
-    inverval: u64,
+    /// Do not end the printed content with a newline, for byte-exact pipelines (the
+    /// contents of multiple codes run together)
+    #[clap(long, env = "QRSCAN_NO_NEWLINE", conflicts_with = "null", value_parser = FalseyValueParser::new())]
+    no_newline: bool,
+
+    /// End the printed content with a NUL byte instead of a newline, to separate the
+    /// contents of multiple codes for `xargs -0`
+    #[clap(long, env = "QRSCAN_NULL", short = '0', value_parser = FalseyValueParser::new())]
+    null: bool,
+
+    /// Fail on content that is not valid in its declared encoding, or UTF-8, instead
+    /// of replacing the malformed sequences
+    #[clap(long, env = "QRSCAN_STRICT_ENCODING", value_parser = FalseyValueParser::new())]
+    strict_encoding: bool,
+
+    /// Fail on QR codes with an error correction block that needed more than half of
+    /// the errors it can fix corrected, as they are likely damaged and may have
+    /// decoded to a wrong content
+    #[clap(long, env = "QRSCAN_STRICT", value_parser = FalseyValueParser::new())]
+    strict: bool,
+
+    /// Print the fields of structured content, like WiFi credentials
+    #[clap(long, env = "QRSCAN_PARSE", value_parser = FalseyValueParser::new())]
+    parse: bool,
+
+    /// Print the URLs percent-decoded, like "%20" as a space (only the http(s),
+    /// mailto, tel and geo contents, and not with --json or --raw)
+    #[clap(long, env = "QRSCAN_URL_DECODE", value_parser = FalseyValueParser::new())]
+    url_decode: bool,
+
+    /// Write the content of the first code as raw bytes to the given path. Use "-"
+    /// to write to stdout instead of printing the content as text
+    #[clap(long, env = "QRSCAN_RAW")]
+    raw: Option<PathBuf>,
+
+    /// Write the content of the codes as text to the given path, one per line (or as
+    /// ended with --no-newline or --null). Use "-" to write to stdout instead of
+    /// printing it. "{name}" gets replaced with the input file name, and "{index}"
+    /// with the number of the code to write each to its own file
+    #[clap(long, env = "QRSCAN_OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Copy the content to the system clipboard (only the first code with --all)
+    #[clap(long, env = "QRSCAN_CLIPBOARD", short, value_parser = FalseyValueParser::new())]
+    clipboard: bool,
+
+    /// Open the content in the default browser if it is an http or https URL
+    #[clap(long, env = "QRSCAN_OPEN", short, value_parser = FalseyValueParser::new())]
+    open: bool,
+
+    /// Ring the terminal bell when a QR code is found
+    #[clap(long, env = "QRSCAN_BEEP", value_parser = FalseyValueParser::new())]
+    beep: bool,
+
+    /// Send a desktop notification with the content of each QR code found
+    #[clap(long, env = "QRSCAN_NOTIFY", value_parser = FalseyValueParser::new())]
+    notify: bool,
+
+    /// Interval between scans in milisecond (also between the scanned frames of a video)
+    #[clap(
+        long,
+        env = "QRSCAN_INTERVAL",
+        short,
+        default_value = "200",
+        overrides_with = "inverval"
+    )]
+    interval: u64,
+
+    /// Deprecated misspelling of --interval
+    #[clap(
+        long,
+        env = "QRSCAN_INVERVAL",
+        hide = true,
+        overrides_with = "interval"
+    )]
+    inverval: Option<u64>,
 
     /// Invert the QR code colors
-    #[clap(long)]
+    #[clap(long, env = "QRSCAN_INVERT_COLORS", value_parser = FalseyValueParser::new())]
     invert_colors: bool,
 
-    /// Specify the QR code foreground color (when exporting image)
-    #[clap(long, default_value = "#000")]
+    /// Specify the QR code foreground color, as a CSS color in sRGB (when exporting
+    /// image)
+    #[clap(long, env = "QRSCAN_FG", default_value = "#000")]
     fg: String,
 
-    /// Specify the QR code background color (when exporting image)
-    #[clap(long, default_value = "#fff")]
+    /// Fill the dark modules with a linear gradient instead of the foreground color,
+    /// as COLOR1,COLOR2,ANGLE with the angle in degrees clockwise from left to right
+    /// (when exporting raster images)
+    #[clap(long, env = "QRSCAN_FG_GRADIENT", value_parser = parse_gradient, conflicts_with = "invert_colors")]
+    fg_gradient: Option<Gradient>,
+
+    /// Specify the QR code background color, as a CSS color in sRGB (when exporting
+    /// image)
+    #[clap(long, env = "QRSCAN_BG", default_value = "#fff")]
     bg: String,
 
+    /// How to write the colors in raster images: sRGB keeps them as in CSS, linear
+    /// darkens the midtones as before (vector images are always in sRGB)
+    #[clap(long, env = "QRSCAN_COLOR_SPACE", value_enum, default_value = "srgb")]
+    color_space: ColorSpaceArg,
+
+    /// Size of each QR code module in pixels (when exporting raster images)
+    #[clap(
+        long,
+        env = "QRSCAN_MODULE_SIZE",
+        default_value = "8",
+        value_parser = clap::value_parser!(u32).range(1..=MAX_MODULE_SIZE as i64)
+    )]
+    module_size: u32,
+
+    /// Exact width and height of the image in pixels, using the largest module size
+    /// that fits and padding the rest (when exporting raster images)
+    #[clap(
+        long,
+        env = "QRSCAN_SIZE",
+        conflicts_with = "module_size",
+        value_parser = clap::value_parser!(u32).range(1..=MAX_IMAGE_SIZE as i64)
+    )]
+    size: Option<u32>,
+
+    /// Resolution in dots per inch to record in the image, for printing at the
+    /// intended physical size (when exporting PNG)
+    #[clap(long, env = "QRSCAN_DPI", value_parser = clap::value_parser!(u32).range(1..))]
+    dpi: Option<u32>,
+
+    /// Shape of the dark modules, keeping the finder patterns square (when exporting
+    /// raster images)
+    #[clap(long, env = "QRSCAN_STYLE", value_enum, default_value = "square")]
+    style: Style,
+
+    /// Error correction level of the generated QR code (when printing or exporting)
+    #[clap(
+        long,
+        env = "QRSCAN_EC_LEVEL",
+        value_enum,
+        default_value = "m",
+        ignore_case = true
+    )]
+    ec_level: ErrorCorrectionLevel,
+
+    /// Scan the generated QR codes back before printing or exporting them, and fail if
+    /// they do not decode to the content
+    #[clap(long, env = "QRSCAN_VERIFY", value_parser = FalseyValueParser::new())]
+    verify: bool,
+
+    /// Split the content to encode across several QR codes with structured append,
+    /// each fitting in --max-version. The export paths must contain "{index}" to be
+    /// replaced with the number of each code
+    #[clap(long, env = "QRSCAN_SPLIT", requires = "encode", value_parser = FalseyValueParser::new())]
+    split: bool,
+
+    /// Generate a Micro QR code, of 11x11 to 17x17 modules, for short contents on tiny
+    /// labels. Not all scanners support them
+    #[clap(long, env = "QRSCAN_MICRO", conflicts_with = "split", value_parser = FalseyValueParser::new())]
+    micro: bool,
+
+    /// Mask pattern of the generated QR code, from 0 to 7 (0 to 3 for Micro QR
+    /// codes), instead of the one that scans best, for reproducible test codes
+    #[clap(long, env = "QRSCAN_MASK", value_parser = clap::value_parser!(u8).range(0..=7))]
+    mask: Option<u8>,
+
+    /// Smallest version of the generated QR code, from 1 (21x21 modules) to 40
+    /// (177x177 modules)
+    #[clap(long, env = "QRSCAN_MIN_VERSION", default_value = "1", value_parser = clap::value_parser!(i16).range(1..=40))]
+    min_version: i16,
+
+    /// Largest version of the generated QR code, failing if the content does not fit
+    #[clap(long, env = "QRSCAN_MAX_VERSION", default_value = "40", value_parser = clap::value_parser!(i16).range(1..=40))]
+    max_version: i16,
+
     /// Do not add quiet zone to the QR code
-    #[clap(long)]
+    #[clap(long, env = "QRSCAN_NO_QUIET_ZONE", value_parser = FalseyValueParser::new())]
     no_quiet_zone: bool,
 
-    /// Export the QR code as ascii text to the given path
-    #[clap(long)]
+    /// Draw the given image over the center of the QR code (when exporting raster
+    /// images). Forces the highest error correction level
+    #[clap(long, env = "QRSCAN_LOGO")]
+    logo: Option<PathBuf>,
+
+    /// Width of the logo in percent of the QR code's width (works with --logo)
+    #[clap(
+        long,
+        env = "QRSCAN_LOGO_SIZE",
+        default_value = "20",
+        requires = "logo",
+        value_parser = clap::value_parser!(u32).range(1..=100)
+    )]
+    logo_size: u32,
+
+    /// Width of the quiet zone around the QR code in modules (0 is the same as
+    /// --no-quiet-zone)
+    #[clap(
+        long,
+        env = "QRSCAN_QUIET_ZONE_SIZE",
+        default_value = "4",
+        conflicts_with = "no_quiet_zone",
+        value_parser = clap::value_parser!(u32).range(0..=MAX_QUIET_ZONE as i64)
+    )]
+    quiet_zone_size: u32,
+
+    /// Fail instead of overwriting the files to export to, or to write the content
+    /// to (existing files are overwritten by default)
+    #[clap(long, env = "QRSCAN_NO_CLOBBER", value_parser = FalseyValueParser::new())]
+    no_clobber: bool,
+
+    /// Export the QR code as ascii text to the given path. In the export paths,
+    /// "{name}" gets replaced with the input file name (without the extension)
+    #[clap(long, env = "QRSCAN_ASCII")]
     ascii: Option<PathBuf>,
 
     /// Export the QR code as svg image to the given path
-    #[clap(long)]
+    #[clap(long, env = "QRSCAN_SVG")]
     svg: Option<PathBuf>,
 
     /// Export the QR code as png image to the given path
-    #[clap(long)]
+    #[clap(long, env = "QRSCAN_PNG")]
     png: Option<PathBuf>,
 
     /// Export the QR code as jpeg image to the given path
-    #[clap(long)]
+    #[clap(long, env = "QRSCAN_JPEG")]
     jpeg: Option<PathBuf>,
-}
 
-fn capture(args: &Args) -> Result<()> {
-    let index = CameraIndex::Index(0);
-    // let format = CameraFormat::new_from(640, 480, FrameFormat::YUYV, 30);
-    let formattyp = RequestedFormatType::None;
-    let requested = RequestedFormat::new::<RgbFormat>(formattyp);
+    /// Quality of the exported jpeg image, from 1 to 100 (lower qualities make
+    /// smaller files, but may blur the code until it is unscannable)
+    #[clap(long, env = "QRSCAN_JPEG_QUALITY", default_value = "95", value_parser = clap::value_parser!(u8).range(1..=100))]
+    jpeg_quality: u8,
 
-    let mut camera = Camera::new(index, requested)?;
-    let mut spinner = 0;
+    /// Export the QR code as lossless webp image to the given path
+    #[clap(long, env = "QRSCAN_WEBP")]
+    webp: Option<PathBuf>,
 
-    let preview = viuer::Config {
-        x: args.preview_x,
-        y: args.preview_y,
-        restore_cursor: false,
-        transparent: false,
-        absolute_offset: true,
-        width: args.preview_w,
-        height: args.preview_h,
-        ..Default::default()
-    };
+    /// Export the QR code as gif image to the given path
+    #[clap(long, env = "QRSCAN_GIF")]
+    gif: Option<PathBuf>,
 
-    camera.open_stream()?;
+    /// Export the QR code as bmp image to the given path
+    #[clap(long, env = "QRSCAN_BMP")]
+    bmp: Option<PathBuf>,
 
-    loop {
-        let frame = camera.frame()?;
-        let image = frame.decode_image::<RgbFormat>()?;
-        let image = DynamicImage::ImageRgb8(image);
+    /// Export the QR code as tiff image to the given path
+    #[clap(long, env = "QRSCAN_TIFF")]
+    tiff: Option<PathBuf>,
 
-        if print_image(args, &image).is_err() {
-            if args.preview {
-                viuer::print(&image.fliph(), &preview)?;
-            } else {
-                eprint!("\rScanning via camera{}", PROGRESS[spinner]);
-                spinner = (spinner + 1) % 4;
-            };
-        } else {
-            break;
-        }
-    }
+    /// Export the QR code as vector pdf document to the given path
+    #[clap(long, env = "QRSCAN_PDF")]
+    pdf: Option<PathBuf>,
 
-    Ok(())
+    /// Export the QR code as vector eps image to the given path
+    #[clap(long, env = "QRSCAN_EPS")]
+    eps: Option<PathBuf>,
+
+    /// Write the exported files into a zip archive at the given path instead, with the
+    /// export paths as the names in the archive
+    #[clap(long, env = "QRSCAN_BUNDLE", conflicts_with = "watch")]
+    bundle: Option<PathBuf>,
+
+    /// Read the default options from the given config file, instead of
+    /// $XDG_CONFIG_HOME/qrscan/config.toml or ~/.config/qrscan/config.toml. The keys
+    /// are the long option names, e.g. `fg = "#00f"` or `no_rotate = true`
+    #[clap(long, env = "QRSCAN_CONFIG", conflicts_with = "no_config")]
+    config: Option<PathBuf>,
+
+    /// Do not read the config file
+    #[clap(long, env = "QRSCAN_NO_CONFIG", value_parser = FalseyValueParser::new())]
+    no_config: bool,
+
+    /// Print the completion script for the given shell and exit
+    #[clap(long, env = "QRSCAN_GENERATE_COMPLETIONS", value_enum, hide = true)]
+    generate_completions: Option<clap_complete::Shell>,
 }
 
-fn scan_stdin(args: &Args) -> Result<()> {
-    let mut buf = vec![];
-    let mut stdin = std::io::stdin().lock();
-    stdin.read_to_end(&mut buf)?;
+#[derive(Debug)]
+struct TimedOut;
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "timed out")
+    }
+}
 
-    let image = ImageReader::new(Cursor::new(buf))
-        .with_guessed_format()?
-        .decode()?;
+impl std::error::Error for TimedOut {}
 
-    print_image(args, &image)
+/// The input is empty, or not an image in a supported format.
+#[derive(Debug)]
+struct NotAnImage(String);
+
+impl std::fmt::Display for NotAnImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
-fn scan_file(args: &Args, path: &PathBuf) -> Result<()> {
-    let image = ImageReader::open(path)?.decode()?;
-    print_image(args, &image)
+impl std::error::Error for NotAnImage {}
+
+#[derive(Debug)]
+struct UnexpectedCount {
+    expected: usize,
+    found: usize,
 }
 
-fn build_binary_image(
-    content: &str,
-    (dr, dg, db, da): (u8, u8, u8, u8),
-    (lr, lg, lb, la): (u8, u8, u8, u8),
-    quiet_zone: bool,
-) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    let img = QrCode::new(content)?
-        .render::<Rgba<u8>>()
-        .quiet_zone(quiet_zone)
-        .dark_color(Rgba([dr, dg, db, da]))
-        .light_color(Rgba([lr, lg, lb, la]))
-        .build();
-    Ok(img)
+impl std::fmt::Display for UnexpectedCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "expected {} QR codes, found {}",
+            self.expected, self.found
+        )
+    }
 }
 
-fn print_image(args: &Args, image: &DynamicImage) -> Result<()> {
-    let image = image.to_luma8();
-    let mut img = rqrr::PreparedImage::prepare(image);
-    let grids = img.detect_grids();
+impl std::error::Error for UnexpectedCount {}
 
-    if let Some(grid) = grids.first() {
-        let (meta, content) = grid.decode()?;
-        eprint!("\r                        \r");
+/// The exit code for the error: 4 when timed out, 5 when no QR code was found, 6
+/// when it could not be decoded, 7 when not the expected number was found, and 1
+/// otherwise.
+fn exit_code(err: &anyhow::Error) -> i32 {
+    if err.is::<TimedOut>() {
+        4
+    } else if let Some(err) = err.downcast_ref::<ScanError>() {
+        match err {
+            ScanError::NoCodeFound => 5,
+            ScanError::DecodeFailed(_) => 6,
+            _ => 1,
+        }
+    } else if err.is::<UnexpectedCount>() {
+        7
+    } else if err.is::<NotAnImage>() {
+        8
+    } else {
+        1
+    }
+}
 
-        // Ansi
-        if args.qr {
-            if args.preview {
-                println!();
-            }
+#[derive(Serialize, Debug)]
+struct JsonCode<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_base64: Option<String>,
+    encoding: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    charset: Option<&'static str>,
+    symbology: &'static str,
+    version: usize,
+    grid_size: usize,
+    ecc_level: u16,
+    mask: u16,
+    bounds: [(i32, i32); 4],
+    content_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capacity_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors_corrected: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_errors: Option<usize>,
+    #[serde(rename = "type")]
+    content_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wifi: Option<JsonWifi>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    structured_append: Option<JsonPart>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix: Option<JsonMatrix>,
+}
 
-            let (dark, light) = if args.invert_colors {
-                (Dark, Light)
-            } else {
-                (Light, Dark)
-            };
+/// A detected QR code that failed to decode, listed with --include-failed.
+#[derive(Serialize, Debug)]
+struct JsonFailedGrid<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<&'a str>,
+    decoded: bool,
+    error: String,
+    bounds: [(i32, i32); 4],
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum JsonGrid<'a> {
+    Decoded(Box<JsonCode<'a>>),
+    Failed(JsonFailedGrid<'a>),
+}
 
-            let image = QrCode::new(&content)?
-                .render::<Dense1x2>()
-                .dark_color(dark)
-                .light_color(light)
-                .quiet_zone(!args.no_quiet_zone)
-                .build();
+/// The modules of a QR code row by row, 1 for the dark ones.
+#[derive(Serialize, Debug)]
+struct JsonMatrix {
+    width: usize,
+    height: usize,
+    modules: Vec<Vec<u8>>,
+}
 
-            println!("{image}");
+impl From<&[Vec<bool>]> for JsonMatrix {
+    fn from(modules: &[Vec<bool>]) -> Self {
+        Self {
+            width: modules.first().map_or(0, Vec::len),
+            height: modules.len(),
+            modules: modules
+                .iter()
+                .map(|row| row.iter().map(|dark| *dark as u8).collect())
+                .collect(),
         }
+    }
+}
 
-        // Metadata
-        if args.metadata {
-            if args.preview || args.qr {
-                println!()
-            };
+#[derive(Serialize, Debug)]
+struct JsonPart {
+    index: u8,
+    total: u8,
+    parity: u8,
+}
 
-            println!("Version: {}", meta.version.0);
-            println!("Grid Size: {}", meta.version.to_size());
-            println!("EC Level: {}", meta.ecc_level);
-            println!("Mask: {}", meta.mask);
+impl From<StructuredAppend> for JsonPart {
+    fn from(part: StructuredAppend) -> Self {
+        Self {
+            index: part.index + 1,
+            total: part.total,
+            parity: part.parity,
         }
+    }
+}
 
-        // Content
-        if !args.no_content {
-            if args.preview || args.qr || args.metadata {
-                println!();
-            };
-            println!("{content}");
+#[derive(Serialize, Debug)]
+struct JsonWifi {
+    ssid: String,
+    auth: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    hidden: bool,
+}
+
+impl From<WifiNetwork> for JsonWifi {
+    fn from(wifi: WifiNetwork) -> Self {
+        Self {
+            ssid: wifi.ssid,
+            auth: wifi.auth,
+            password: wifi.password,
+            hidden: wifi.hidden,
         }
+    }
+}
 
-        // Output image colors
-        let (dark, light) = if args.invert_colors {
-            (&args.bg, &args.fg)
-        } else {
-            (&args.fg, &args.bg)
-        };
+/// A camera and the formats it supports, as listed with --list-cameras.
+#[derive(Serialize, Debug)]
+struct JsonCamera {
+    /// The value to pass to --device.
+    index: String,
+    name: String,
+    formats: Vec<JsonCameraFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-        // SVG
-        if let Some(path) = args.svg.as_ref() {
-            let image = QrCode::new(&content)?
-                .render()
-                .dark_color(svg::Color(dark))
-                .light_color(svg::Color(light))
-                .quiet_zone(!args.no_quiet_zone)
-                .build()
-                .into_bytes();
+#[derive(Serialize, Debug)]
+struct JsonCameraFormat {
+    format: String,
+    width: u32,
+    height: u32,
+    frame_rates: Vec<u32>,
+}
 
-            if path.to_str() == Some("-") {
-                std::io::stdout().write_all(&image)?;
-            } else {
-                std::fs::write(path, &image)?
-            }
+impl<'a> JsonCode<'a> {
+    fn new(
+        code: &'a DecodedCode,
+        path: Option<&'a Path>,
+        page: Option<usize>,
+        timestamp: Option<&'a str>,
+    ) -> Self {
+        let (content, content_base64, encoding) = match code.text() {
+            Some(content) => (Some(content), None, "utf-8"),
+            None => (None, Some(base64::encode(&code.content)), "base64"),
+        };
+        let wifi = content.as_deref().and_then(qrscan::parse_wifi);
+
+        Self {
+            path,
+            page,
+            timestamp,
+            decoded: None,
+            content,
+            content_base64,
+            encoding,
+            charset: code.encoding.map(|encoding| encoding.name()),
+            symbology: code.symbology.as_str(),
+            version: code.version,
+            grid_size: code.grid_size(),
+            ecc_level: code.ecc_level,
+            mask: code.mask,
+            bounds: code.bounds,
+            content_bytes: code.content.len(),
+            capacity_bytes: code.capacity(),
+            errors_corrected: code.corrections.map(|corrections| corrections.errors),
+            max_errors: code.corrections.map(|corrections| corrections.max_errors),
+            content_type: content_type(code),
+            wifi: wifi.map(Into::into),
+            structured_append: code.structured_append.map(Into::into),
+            matrix: None,
         }
+    }
+}
 
-        // Ascii
-        if let Some(path) = args.ascii.as_ref() {
-            let image = QrCode::new(&content)?
-                .render::<char>()
-                .module_dimensions(2, 1)
-                .quiet_zone(!args.no_quiet_zone)
-                .build()
-                .into_bytes();
+fn camera_index(device: &str) -> Result<CameraIndex> {
+    if let Ok(index) = device.parse() {
+        return Ok(CameraIndex::Index(index));
+    }
 
-            if path.to_str() == Some("-") {
-                std::io::stdout().write_all(&image)?;
-            } else {
-                std::fs::write(path, &image)?
-            }
-        }
+    if let Some(Ok(index)) = device.strip_prefix("/dev/video").map(str::parse) {
+        return Ok(CameraIndex::Index(index));
+    }
 
-        // RGB colors
-        let dark = dark.parse::<Color>()?.to_linear_rgba_u8();
-        let light = light.parse::<Color>()?.to_linear_rgba_u8();
+    nokhwa::query(ApiBackend::Auto)?
+        .into_iter()
+        .find(|info| info.human_name() == device)
+        .map(|info| info.index().clone())
+        .ok_or_else(|| anyhow::anyhow!("{device}: No such camera"))
+}
 
-        // PNG
-        if let Some(path) = args.png.as_ref() {
-            let image = build_binary_image(&content, dark, light, !args.no_quiet_zone)?;
-            let bytes = image.as_bytes();
+fn list_cameras(args: &Args) -> Result<()> {
+    let cameras = query_cameras()?;
+    if args.json {
+        println!("{}", serde_json::to_string(&cameras)?);
+        return Ok(());
+    }
 
-            let mut result: Vec<u8> = Default::default();
-            let encoder = PngEncoder::new(&mut result);
-            encoder.write_image(
-                bytes,
-                image.width(),
-                image.height(),
-                ColorType::Rgba8,
-            )?;
+    for (i, camera) in cameras.iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        println!("{}: {}", camera.index, camera.name);
 
-            if path.to_str() == Some("-") {
-                std::io::stdout().write_all(&result)?;
-            } else {
-                std::fs::write(path, result)?
+        for format in camera.formats.iter() {
+            for fps in format.frame_rates.iter() {
+                println!(
+                    "  {} {}x{} @ {fps}fps",
+                    format.format, format.width, format.height
+                );
             }
         }
+        if let Some(err) = camera.error.as_ref() {
+            eprintln!("  error: qrscan: {err}");
+        }
+    }
 
-        // JPEG
-        if let Some(path) = args.jpeg.as_ref() {
-            let image = build_binary_image(&content, dark, light, !args.no_quiet_zone)?;
-            let bytes = image.as_bytes();
+    Ok(())
+}
 
-            let mut result: Vec<u8> = Default::default();
-            let mut encoder = JpegEncoder::new(&mut result);
-            encoder.encode(bytes, image.width(), image.height(), ColorType::Rgba8)?;
+/// The available cameras, with the frame rates of each of their formats and
+/// resolutions.
+fn query_cameras() -> Result<Vec<JsonCamera>> {
+    let mut cameras = vec![];
+    for info in nokhwa::query(ApiBackend::Auto)? {
+        let formattyp = RequestedFormatType::None;
+        let requested = RequestedFormat::new::<RgbFormat>(formattyp);
+        let result = Camera::new(info.index().clone(), requested)
+            .and_then(|mut camera| camera.compatible_camera_formats());
 
-            if path.to_str() == Some("-") {
-                std::io::stdout().write_all(&result)?;
-            } else {
-                std::fs::write(path, result)?
+        let mut formats: Vec<JsonCameraFormat> = vec![];
+        let error = match result {
+            Ok(camera_formats) => {
+                for format in camera_formats {
+                    let (name, width, height) =
+                        (format.format().to_string(), format.width(), format.height());
+                    match formats.iter_mut().find(|known| {
+                        (&known.format, known.width, known.height)
+                            == (&name, width, height)
+                    }) {
+                        Some(known) => known.frame_rates.push(format.frame_rate()),
+                        None => formats.push(JsonCameraFormat {
+                            format: name,
+                            width,
+                            height,
+                            frame_rates: vec![format.frame_rate()],
+                        }),
+                    }
+                }
+                None
             }
-        }
-    } else {
-        std::thread::sleep(Duration::from_millis(args.inverval));
-        anyhow::bail!("failed to read")
-    };
+            Err(err) => Some(err.to_string()),
+        };
 
-    Ok(())
+        cameras.push(JsonCamera {
+            index: info.index().to_string(),
+            name: info.human_name(),
+            formats,
+            error,
+        });
+    }
+    Ok(cameras)
 }
 
-fn main() {
-    let args = Args::parse();
-    let mut rc = 0;
+fn requested_format_type(args: &Args, frame_format: FrameFormat) -> RequestedFormatType {
+    match (args.camera_width, args.camera_height, args.camera_fps) {
+        (Some(width), Some(height), fps) => {
+            let fps = fps.unwrap_or(30);
+            let format = CameraFormat::new_from(width, height, frame_format, fps);
+            RequestedFormatType::Closest(format)
+        }
+        (_, _, Some(fps)) => RequestedFormatType::HighestFrameRate(fps),
+        _ => RequestedFormatType::None,
+    }
+}
 
-    if let Some(path) = args.image.as_ref() {
-        if path.to_str() == Some("-") {
-            if let Err(err) = scan_stdin(&args) {
-                eprintln!("error: qrscan: {err}");
-                rc = 1;
+fn open_camera(
+    args: &Args,
+    index: &CameraIndex,
+    default_format: bool,
+) -> Result<Camera> {
+    let frame_formats = match args.frame_format {
+        Some(format) => vec![format.into()],
+        None => FRAME_FORMATS.to_vec(),
+    };
+
+    let mut result = Err(anyhow::anyhow!("no frame format to try"));
+    for frame_format in frame_formats {
+        let formattyp = if default_format {
+            RequestedFormatType::None
+        } else {
+            requested_format_type(args, frame_format)
+        };
+        let formats = [frame_format];
+        let requested = RequestedFormat::with_formats(formattyp, &formats);
+
+        result = Camera::new(index.clone(), requested)
+            .and_then(|mut camera| camera.open_stream().map(|_| camera))
+            .map_err(|err| anyhow::anyhow!("{frame_format}: {err}"));
+
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+/// Open the camera, falling back to the default format if the requested one is not
+/// supported, and retrying with a growing delay on failure.
+fn open_camera_with_retries(args: &Args, index: &CameraIndex) -> Result<Camera> {
+    let mut retries = 0;
+    let (camera, rejected) = loop {
+        // The error of the requested format, if the default one opened instead
+        let result = match open_camera(args, index, false) {
+            Err(err) if args.camera_width.is_some() || args.camera_fps.is_some() => {
+                open_camera(args, index, true).map(|camera| (camera, Some(err)))
             }
-        } else if !path.exists() {
-            eprintln!("error: qrscan: {}: No such file", path.display());
-            rc = 3;
-        } else if path.is_dir() {
-            eprintln!(
-                "error: qrscan: cannot scan {}: Is a directory",
-                path.display()
+            result => result.map(|camera| (camera, None)),
+        };
+
+        match result {
+            Err(err) if retries < args.open_retries => {
+                let delay = OPEN_RETRY_DELAY * 2u32.pow(retries.min(5));
+                retries += 1;
+                debug(
+                    args,
+                    1,
+                    format_args!(
+                        "{err}: retrying in {delay:?} ({retries}/{})",
+                        args.open_retries
+                    ),
+                );
+                std::thread::sleep(delay);
+            }
+            result => break result?,
+        }
+    };
+
+    if let Some(err) = rejected {
+        eprintln!("warning: {err}: falling back to the default format");
+    }
+    Ok(camera)
+}
+
+/// Open the camera of --device, retrying as configured.
+fn open_device(args: &Args) -> Result<Camera> {
+    if args.device == "auto" {
+        probe_cameras(args)
+    } else {
+        open_camera_with_retries(args, &camera_index(&args.device)?)
+    }
+}
+
+/// Keep trying to open the camera of --device until it gets plugged in, or the
+/// timeout passes.
+fn wait_for_device(args: &Args, timeout: Option<Duration>) -> Result<Camera> {
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        let err = match open_device(args) {
+            Ok(camera) => return Ok(camera),
+            Err(err) => err,
+        };
+        if let Some(timeout) = timeout.filter(|&timeout| start.elapsed() >= timeout) {
+            let secs = timeout.as_secs();
+            return Err(anyhow::Error::from(TimedOut)
+                .context(format!("no camera was plugged in within {secs} seconds")));
+        }
+
+        if !waiting && !args.quiet {
+            eprintln!("Waiting for the camera to be plugged in");
+        }
+        waiting = true;
+        debug(args, 1, format_args!("{err}: waiting for the camera"));
+        std::thread::sleep(DEVICE_POLL_INTERVAL);
+    }
+}
+
+/// Open the first camera that gives a non-blank frame within the probe window, or
+/// camera 0 if none does.
+fn probe_cameras(args: &Args) -> Result<Camera> {
+    let cameras = nokhwa::query(ApiBackend::Auto).unwrap_or_else(|err| {
+        debug(args, 1, format_args!("failed to list the cameras: {err}"));
+        vec![]
+    });
+
+    for info in cameras {
+        let (index, name) = (info.index(), info.human_name());
+        let mut camera = match open_camera(args, index, false) {
+            Ok(camera) => camera,
+            Err(err) => {
+                debug(args, 1, format_args!("camera {index} ({name}): {err}"));
+                continue;
+            }
+        };
+
+        let start = Instant::now();
+        while start.elapsed() < PROBE_WINDOW {
+            let Ok(frame) = camera.frame() else {
+                break;
+            };
+            let Ok(image) = frame.decode_image::<RgbFormat>() else {
+                break;
+            };
+            if !is_blank(&DynamicImage::ImageRgb8(image)) {
+                debug(args, 1, format_args!("using camera {index} ({name})"));
+                return Ok(camera);
+            }
+        }
+        debug(
+            args,
+            1,
+            format_args!("camera {index} ({name}): no non-blank frame"),
+        );
+    }
+
+    debug(args, 1, format_args!("falling back to camera 0"));
+    open_camera_with_retries(args, &CameraIndex::Index(0))
+}
+
+/// Whether the frame is of a single flat color, as from an idle virtual camera.
+fn is_blank(image: &DynamicImage) -> bool {
+    let luma = image.to_luma8();
+    let count = luma.len() as f64;
+    if count == 0.0 {
+        return true;
+    }
+    let mean = luma.iter().map(|&l| l as f64).sum::<f64>() / count;
+    let variance = luma.iter().map(|&l| (l as f64 - mean).powi(2)).sum::<f64>() / count;
+    variance.sqrt() < BLANK_DEVIATION
+}
+
+/// The contents decoded so far, persisted in a file with one base64 encoded content
+/// per line.
+struct History {
+    path: PathBuf,
+    seen: BTreeSet<Vec<u8>>,
+}
+
+impl History {
+    /// Load the history from the file, if it exists, or start afresh.
+    fn load(path: &Path, reset: bool) -> Result<Self> {
+        let mut history = Self {
+            path: path.to_path_buf(),
+            seen: BTreeSet::new(),
+        };
+
+        if reset {
+            history.save()?;
+            return Ok(history);
+        }
+
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(history)
+            }
+            Err(err) => anyhow::bail!("{}: {err}", path.display()),
+        };
+
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let content = base64::decode(line)
+                .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+            history.seen.insert(content);
+        }
+
+        Ok(history)
+    }
+
+    /// Add the content, saving the history if it is new. Returns whether it is new.
+    fn insert(&mut self, content: &[u8]) -> Result<bool> {
+        if !self.seen.insert(content.to_vec()) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Write to a temporary file first and then rename it, so that an interrupted
+    /// write does not corrupt the history.
+    fn save(&self) -> Result<()> {
+        let mut text = String::new();
+        for content in self.seen.iter() {
+            text.push_str(&base64::encode(content));
+            text.push('\n');
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        std::fs::write(&tmp, text)
+            .and_then(|()| std::fs::rename(&tmp, &self.path))
+            .map_err(|err| anyhow::anyhow!("{}: {err}", self.path.display()))
+    }
+}
+
+fn capture(args: &Args) -> Result<()> {
+    let mut history = args
+        .dedup_file
+        .as_ref()
+        .map(|path| History::load(path, args.reset))
+        .transpose()?;
+
+    let mut camera = match args.wait_for_device {
+        Some(wait) => wait_for_device(args, wait.map(Duration::from_secs))?,
+        None => open_device(args)?,
+    };
+    let mut spinner = 0;
+
+    // The first frames are often dark or blurry during auto-exposure
+    debug(args, 1, format_args!("skipping {} frames", args.warmup));
+    for _ in 0..args.warmup {
+        camera.frame()?;
+    }
+
+    let (columns, rows) = viuer::terminal_size();
+    let preview = viuer::Config {
+        x: args.preview_x,
+        y: args.preview_y,
+        restore_cursor: false,
+        transparent: false,
+        absolute_offset: true,
+        width: args.preview_w.map(|width| width.resolve(columns)),
+        height: args.preview_h.map(|height| height.resolve(rows)),
+        ..Default::default()
+    };
+
+    // The camera and the preview stay the same across the scans, only the frames change
+    let start = Instant::now();
+    let timeout = args.timeout.map(Duration::from_secs);
+
+    // When each code was last seen, in watch mode
+    let mut seen: HashMap<Vec<u8>, Instant> = HashMap::new();
+    let mut found = false;
+
+    loop {
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            clear_progress(args);
+            if found {
+                return Ok(());
+            }
+            return Err(TimedOut.into());
+        }
+
+        // Frames queue up while scanning and sleeping
+        for _ in 0..args.drop_frames {
+            camera.frame()?;
+        }
+
+        let frame = camera.frame()?;
+        let image = frame.decode_image::<RgbFormat>()?;
+        let image = DynamicImage::ImageRgb8(image);
+
+        let mut codes = scan_image(args, &image).unwrap_or_default();
+
+        if args.watch {
+            seen.retain(|_, at| at.elapsed() < WATCH_DEBOUNCE);
+            codes.retain(|code| {
+                seen.insert(code.content.clone(), Instant::now()).is_none()
+            });
+        }
+
+        if let Some(history) = history.as_mut() {
+            let mut new = vec![];
+            for code in codes {
+                if history.insert(&code.content)? {
+                    new.push(code);
+                }
+            }
+            codes = new;
+        }
+
+        if !codes.is_empty() {
+            if let Some(path) = args.save_frame.as_ref() {
+                image.save_with_format(frame_path(path), ImageFormat::Png)?;
+            }
+            print_codes(args, &codes, &[], None, None)?;
+            found = true;
+
+            if !args.watch {
+                break;
+            }
+        } else {
+            if args.preview && use_color(args) {
+                viuer::print(&image.fliph(), &preview)?;
+            } else if !args.quiet {
+                eprint!("\rScanning via camera{}", PROGRESS[spinner]);
+                spinner = (spinner + 1) % 4;
+            };
+            std::thread::sleep(Duration::from_millis(args.interval));
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace "{timestamp}" in the frame path with the current local time.
+fn frame_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(template) if template.contains("{timestamp}") => {
+            let now = chrono::Local::now().format("%Y%m%dT%H%M%S%.3f");
+            template.replace("{timestamp}", &now.to_string()).into()
+        }
+        _ => path.into(),
+    }
+}
+
+/// Read stdin to the end, giving up after --stdin-timeout.
+fn read_stdin(args: &Args) -> Result<Vec<u8>> {
+    let Some(timeout) = args.stdin_timeout else {
+        let mut buf = vec![];
+        std::io::stdin().lock().read_to_end(&mut buf)?;
+        return Ok(buf);
+    };
+
+    // The thread stays blocked on a stalled pipe until the process exits
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = vec![];
+        let result = std::io::stdin().lock().read_to_end(&mut buf);
+        sender.send(result.map(|_| buf)).ok();
+    });
+
+    match receiver.recv_timeout(Duration::from_secs(timeout)) {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(anyhow::Error::from(TimedOut)
+            .context(format!("stdin was not closed within {timeout} seconds"))),
+    }
+}
+
+fn scan_stdin(args: &Args, path: &Path) -> Result<()> {
+    let buf = read_stdin(args)?;
+
+    if buf.is_empty() {
+        return Err(NotAnImage("stdin is empty, expected an image".into()).into());
+    }
+
+    // Likely piped by mistake, instead of to --encode
+    let text = std::str::from_utf8(&buf).is_ok();
+    let image = decode_buffer(args, buf).map_err(|err| {
+        debug(args, 1, format_args!("{err}"));
+        let message = if text {
+            "stdin contained text, not an image (use --encode - to generate a QR code from it)"
+        } else {
+            "stdin did not contain a decodable image"
+        };
+        NotAnImage(message.into())
+    })?;
+    print_image(args, &image, Some(path))
+}
+
+/// Scan each image of the --stream on stdin, skipping the ones without codes. Keeps
+/// going on the images that fail, and returns the exit code of the first failure.
+fn scan_stream(args: &Args) -> Result<i32> {
+    let mut stdin = std::io::stdin().lock();
+    let mut frame = 0;
+    let mut rc = 0;
+    while let Some(buf) = read_frame(&mut stdin)? {
+        frame += 1;
+        let result =
+            decode_buffer(args, buf).and_then(|image| print_image(args, &image, None));
+        match result {
+            Err(err) if matches!(err.downcast_ref(), Some(ScanError::NoCodeFound)) => {}
+            Err(err) => {
+                eprintln!("error: qrscan: image {frame}: {err}");
+                if rc == 0 {
+                    rc = exit_code(&err);
+                }
+            }
+            Ok(()) => {}
+        }
+        std::io::stdout().flush()?;
+    }
+    Ok(rc)
+}
+
+/// Read the next image of the stream: a whole PNG image, split at its IEND chunk, or
+/// the number of bytes it is prefixed with. Returns `None` at the end of the stream.
+fn read_frame(reader: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let truncated = || anyhow::anyhow!("stdin ended in the middle of an image");
+    let not_an_image = || {
+        NotAnImage("stdin did not contain PNG images or length-prefixed images".into())
+    };
+
+    let mut buf = vec![];
+    reader.take(4).read_to_end(&mut buf)?;
+    match buf.len() {
+        0 => return Ok(None),
+        4 => {}
+        _ => return Err(truncated()),
+    }
+
+    // Read exactly `len` more bytes into the frame
+    let mut read = |buf: &mut Vec<u8>, len: u64| -> Result<()> {
+        let start = buf.len();
+        reader.take(len).read_to_end(buf)?;
+        if ((buf.len() - start) as u64) < len {
+            return Err(truncated());
+        }
+        Ok(())
+    };
+
+    if buf != PNG_SIGNATURE[..4] {
+        let len = u32::from_be_bytes(buf[..].try_into()?) as u64;
+        if len > MAX_FRAME_SIZE {
+            return Err(not_an_image().into());
+        }
+        buf.clear();
+        read(&mut buf, len)?;
+        return Ok(Some(buf));
+    }
+
+    read(&mut buf, (PNG_SIGNATURE.len() - 4) as u64)?;
+    if buf != PNG_SIGNATURE {
+        return Err(not_an_image().into());
+    }
+    loop {
+        // The chunk length and type, then its data and CRC
+        let start = buf.len();
+        read(&mut buf, 8)?;
+        let len = u32::from_be_bytes(buf[start..start + 4].try_into()?) as u64;
+        let end = &buf[start + 4..start + 8] == b"IEND";
+
+        if buf.len() as u64 + len + 4 > MAX_FRAME_SIZE {
+            return Err(not_an_image().into());
+        }
+        read(&mut buf, len + 4)?;
+        if end {
+            return Ok(Some(buf));
+        }
+    }
+}
+
+fn scan_url(args: &Args, url: &str) -> Result<()> {
+    let response = match ureq::get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            anyhow::bail!("HTTP {code} {}", response.status_text())
+        }
+        Err(ureq::Error::Transport(err)) => return Err(err.into()),
+    };
+
+    let mut buf = vec![];
+    response.into_reader().read_to_end(&mut buf)?;
+
+    let image = decode_buffer(args, buf)?;
+    print_image(args, &image, Some(Path::new(url)))
+}
+
+/// Decode the image, guessing the format from its content unless given.
+fn decode_buffer(args: &Args, buf: Vec<u8>) -> Result<DynamicImage> {
+    single_page(args)?;
+    let mut reader = ImageReader::new(Cursor::new(buf.as_slice()));
+    match args.input_format {
+        Some(format) => reader.set_format(format.into()),
+        None => reader = reader.with_guessed_format()?,
+    }
+    Ok(orient(args, reader.decode()?, &buf))
+}
+
+/// Fail on --page beyond the first one, for the images that have no other pages.
+fn single_page(args: &Args) -> Result<()> {
+    match args.page {
+        Some(page) if page > 1 => {
+            anyhow::bail!("page {page} is out of range, there is 1 page")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Turn the photo the way it was taken, as cameras store it unrotated with an EXIF
+/// orientation.
+fn orient(args: &Args, image: DynamicImage, jpeg: &[u8]) -> DynamicImage {
+    match exif::exif_orientation(jpeg) {
+        Some(orientation) if orientation != 1 => {
+            debug(
+                args,
+                2,
+                format_args!("applying EXIF orientation {orientation}"),
             );
-            rc = 2;
-        } else if let Err(err) = scan_file(&args, path) {
-            eprintln!("error: qrscan: {err}");
-            rc = 1;
+            exif::apply_orientation(image, orientation)
         }
-    } else if let Err(err) = capture(&args) {
-        eprintln!("error: qrscan: {err}");
-        rc = 1;
+        _ => image,
+    }
+}
+
+/// The URL to download the image from, if the path is an http(s) URL.
+fn url_of(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// The codes found in a file, on each page of PDF documents.
+struct Scanned {
+    codes: Vec<DecodedCode>,
+    failed: Vec<FailedGrid>,
+    page: Option<usize>,
+}
+
+fn print_scanned(args: &Args, path: &Path, scanned: Vec<Scanned>) -> Result<()> {
+    for scanned in scanned {
+        print_codes(
+            args,
+            &scanned.codes,
+            &scanned.failed,
+            Some(path),
+            scanned.page,
+        )?;
+    }
+    Ok(())
+}
+
+/// The "Scanned 3/10 files..." line while scanning multiple files, shown only when
+/// both stdout and stderr are terminals and not with --quiet. It is drawn by hand,
+/// not with indicatif, as it is a single line cleared before each printed code.
+struct Progress {
+    total: usize,
+    shown: bool,
+    counts: Mutex<ProgressCounts>,
+}
+
+#[derive(Default)]
+struct ProgressCounts {
+    decoded: usize,
+    succeeded: usize,
+    failed: usize,
+}
+
+impl Progress {
+    fn new(args: &Args, total: usize) -> Self {
+        let shown = total > 1
+            && !args.quiet
+            && std::io::stdout().is_terminal()
+            && std::io::stderr().is_terminal();
+        let progress = Self {
+            total,
+            shown,
+            counts: Mutex::default(),
+        };
+        progress.draw(&ProgressCounts::default());
+        progress
+    }
+
+    /// Count a file as decoded, before its codes are printed.
+    fn decoded(&self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.decoded += 1;
+        self.draw(&counts);
+    }
+
+    /// Count a file as done, after its codes or error are printed.
+    fn done(&self, succeeded: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        if succeeded {
+            counts.succeeded += 1;
+        } else {
+            counts.failed += 1;
+        }
+        self.draw(&counts);
+    }
+
+    fn draw(&self, counts: &ProgressCounts) {
+        if self.shown {
+            let done = counts.decoded.max(counts.succeeded + counts.failed);
+            eprint!(
+                "\r\x1b[KScanned {done}/{} files: {} succeeded, {} failed",
+                self.total, counts.succeeded, counts.failed
+            );
+        }
+    }
+
+    /// Clear the line, before printing over it.
+    fn clear(&self) {
+        if self.shown {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+/// Scan the file for codes, without printing them.
+fn decode_file(args: &Args, path: &Path) -> Result<Vec<Scanned>> {
+    let mut reader = ImageReader::open(path)?;
+    if let Some(format) = args.input_format {
+        reader.set_format(format.into());
+    } else if is_pdf(path)? {
+        return scan_pdf(args, path);
+    } else if is_heif(path)? {
+        single_page(args)?;
+        let (codes, failed) = grid_codes(args, &decode_heif(args, path)?)?;
+        return Ok(vec![Scanned {
+            codes,
+            failed,
+            page: None,
+        }]);
+    } else if is_video(path) {
+        let codes = scan_video(args, path)?;
+        let failed = vec![];
+        return Ok(vec![Scanned {
+            codes,
+            failed,
+            page: None,
+        }]);
+    } else {
+        reader = reader.with_guessed_format()?;
+    }
+
+    let format = reader.format();
+    if format != Some(ImageFormat::Gif) {
+        single_page(args)?;
+    }
+
+    let (codes, failed) = match format {
+        Some(ImageFormat::Gif) => (scan_gif(args, path)?, vec![]),
+        Some(ImageFormat::Jpeg) => {
+            let mut head = vec![];
+            File::open(path)?
+                .take(JPEG_HEAD_SIZE)
+                .read_to_end(&mut head)?;
+            grid_codes(
+                args,
+                &orient(args, decode_image(args, path, reader)?, &head),
+            )?
+        }
+        _ => grid_codes(args, &decode_image(args, path, reader)?)?,
+    };
+    Ok(vec![Scanned {
+        codes,
+        failed,
+        page: None,
+    }])
+}
+
+/// Decode the image as the format the reader guessed from the content, or from the
+/// extension if the content was not recognized. If that fails, fall back to the other
+/// one, unless with --input-format.
+fn decode_image(
+    args: &Args,
+    path: &Path,
+    reader: ImageReader<BufReader<File>>,
+) -> Result<DynamicImage> {
+    let mut head = vec![];
+    File::open(path)?.take(64).read_to_end(&mut head)?;
+    let by_content = image::guess_format(&head).ok();
+    let by_extension = ImageFormat::from_path(path).ok();
+    let method = |format: Option<ImageFormat>| {
+        if args.input_format.is_some() {
+            "as given with --input-format"
+        } else if format == by_content {
+            "by its content"
+        } else {
+            "by its extension"
+        }
+    };
+
+    let format = reader.format();
+    let err = match reader.decode() {
+        Ok(image) => {
+            debug_decoded(args, format, method(format));
+            return Ok(image);
+        }
+        Err(err) if args.input_format.is_some() => return Err(err.into()),
+        Err(err) => err,
+    };
+
+    for fallback in [by_content, by_extension] {
+        let Some(fallback) = fallback.filter(|&fallback| Some(fallback) != format)
+        else {
+            continue;
+        };
+        debug(
+            args,
+            1,
+            format_args!("failed to decode the image {}: {err}", method(format)),
+        );
+        let mut reader = ImageReader::open(path)?;
+        reader.set_format(fallback);
+        if let Ok(image) = reader.decode() {
+            debug_decoded(args, Some(fallback), method(Some(fallback)));
+            return Ok(image);
+        }
+    }
+    Err(err.into())
+}
+
+/// Report how the image was decoded.
+fn debug_decoded(args: &Args, format: Option<ImageFormat>, method: &str) {
+    let name = format.map_or("unknown", |format| format.extensions_str()[0]);
+    debug(
+        args,
+        1,
+        format_args!("decoded the image as {name} {method}"),
+    );
+}
+
+/// Decode the files in parallel, --jobs at a time, in the order of the paths. PDF
+/// documents and the other paths are left out as `None`, to scan one by one.
+fn decode_files(
+    args: &Args,
+    paths: &[PathBuf],
+    progress: &Progress,
+) -> Vec<Option<Result<Vec<Scanned>>>> {
+    let parallel = |path: &PathBuf| {
+        path.is_file() && (args.input_format.is_some() || !is_pdf(path).unwrap_or(true))
+    };
+
+    let count = paths.iter().filter(|path| parallel(path)).count();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0) as usize)
+        .build();
+    match pool {
+        Ok(pool) if count > 1 && args.jobs != Some(1) => pool.install(|| {
+            paths
+                .par_iter()
+                .map(|path| {
+                    parallel(path).then(|| {
+                        let decoded = decode_file(args, path);
+                        progress.decoded();
+                        decoded
+                    })
+                })
+                .collect()
+        }),
+        _ => paths.iter().map(|_| None).collect(),
+    }
+}
+
+/// Scan the frames of the (possibly animated) GIF image.
+fn scan_gif(args: &Args, path: &Path) -> Result<Vec<DecodedCode>> {
+    let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+    let frames = decoder
+        .into_frames()
+        .map(|frame| Ok(DynamicImage::ImageRgba8(frame?.into_buffer())));
+
+    scan_frames(args, frames)
+}
+
+/// Scan the frames until one has codes, or all of them with --all, skipping the codes
+/// already found on the previous frames.
+fn scan_frames(
+    args: &Args,
+    frames: impl Iterator<Item = Result<DynamicImage>>,
+) -> Result<Vec<DecodedCode>> {
+    if let Some(page) = args.page {
+        let mut count = 0;
+        for image in frames {
+            count += 1;
+            if count == page {
+                return scan_image(args, &image?);
+            }
+        }
+        anyhow::bail!("frame {page} is out of range, there are {count} frames");
+    }
+
+    let mut codes: Vec<DecodedCode> = vec![];
+    for image in frames {
+        for code in scan_image(args, &image?).unwrap_or_default() {
+            if !codes.iter().any(|found| found.content == code.content) {
+                codes.push(code);
+            }
+        }
+
+        if !codes.is_empty() && !args.all {
+            break;
+        }
+    }
+
+    if codes.is_empty() {
+        return Err(ScanError::NoCodeFound.into());
+    }
+
+    Ok(codes)
+}
+
+/// Whether the file is a video, judging by its extension.
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|v| v.eq_ignore_ascii_case(ext)))
+}
+
+/// Decode a frame of the video every --interval milliseconds with ffmpeg, and scan
+/// them like the frames of a GIF image.
+fn scan_video(args: &Args, path: &Path) -> Result<Vec<DecodedCode>> {
+    let probe = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height", "-of", "csv=p=0:s=x"])
+        .arg(path)
+        .output()
+        .map_err(|err| anyhow::anyhow!("cannot scan videos: ffprobe: {err}"))?;
+
+    if !probe.status.success() {
+        let err = String::from_utf8_lossy(&probe.stderr);
+        anyhow::bail!("ffprobe: {}", err.trim());
+    }
+
+    let (width, height) = std::str::from_utf8(&probe.stdout)
+        .ok()
+        .and_then(|out| out.lines().next()?.split_once('x'))
+        .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("no video stream"))?;
+
+    // Raw frames are simpler to split than encoded ones. Not rotating them keeps the
+    // probed dimensions right, and the codes are found in any orientation anyway.
+    let ffmpeg = Command::new("ffmpeg")
+        .args(["-v", "error", "-noautorotate", "-i"])
+        .arg(path)
+        .args(["-map", "0:v:0", "-vf"])
+        .arg(format!("fps=1000/{}", args.interval.max(1)))
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("cannot scan videos: ffmpeg: {err}"))?;
+
+    scan_raw_frames(args, ffmpeg, width, height)
+}
+
+/// Scan the raw RGB frames of the given size that ffmpeg writes on its stdout. It is
+/// stopped once done, and its errors only reported if it ended the frames early.
+fn scan_raw_frames(
+    args: &Args,
+    mut ffmpeg: Child,
+    width: u32,
+    height: u32,
+) -> Result<Vec<DecodedCode>> {
+    let mut stdout = ffmpeg.stdout.take().expect("stdout is piped");
+    let mut buf = vec![0; width as usize * height as usize * 3];
+    let mut ended = false;
+    let frames = std::iter::from_fn(|| {
+        if stdout.read_exact(&mut buf).is_err() {
+            ended = true;
+            return None;
+        }
+        let image = RgbImage::from_raw(width, height, buf.clone())?;
+        Some(Ok(DynamicImage::ImageRgb8(image)))
+    });
+
+    let result = scan_frames(args, frames);
+    drop(stdout);
+    if !ended {
+        // Stop decoding the rest of the video
+        let _ = ffmpeg.kill();
+    }
+
+    let output = ffmpeg.wait_with_output()?;
+    if ended && result.is_err() && !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("ffmpeg: {}", err.trim());
+    }
+
+    result
+}
+
+/// Whether the file is a PDF document, judging by its magic bytes.
+fn is_pdf(path: &Path) -> Result<bool> {
+    let mut magic = [0; 5];
+    let mut file = File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok() && &magic == b"%PDF-")
+}
+
+/// Whether the file is a HEIF image, which the image crate cannot decode.
+fn is_heif(path: &Path) -> Result<bool> {
+    let mut magic = [0; 12];
+    let mut file = File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok()
+        && &magic[4..8] == b"ftyp"
+        && HEIF_BRANDS.contains(&&magic[8..]))
+}
+
+/// Convert the HEIF image to PNG with heif-convert of libheif, applying its rotation,
+/// and decode that. The conversion goes to a private temporary directory, removed
+/// with the other files heif-convert writes next to the PNG, like its depth images.
+fn decode_heif(args: &Args, path: &Path) -> Result<DynamicImage> {
+    let dir = tempfile::tempdir()?;
+    let png = dir.path().join("image.png");
+
+    let output = Command::new("heif-convert")
+        .arg(path)
+        .arg(&png)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| {
+            anyhow::anyhow!("cannot scan HEIF images: heif-convert: {err}")
+        })?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("heif-convert: {}", err.trim());
+    }
+
+    let image = image::open(&png)?;
+    debug(
+        args,
+        1,
+        format_args!("decoded the image as heif with heif-convert"),
+    );
+    Ok(image)
+}
+
+/// Render the pages of the PDF document one at a time, and scan each of them.
+fn scan_pdf(args: &Args, path: &Path) -> Result<Vec<Scanned>> {
+    let bindings = Pdfium::bind_to_system_library()
+        .map_err(|err| anyhow::anyhow!("cannot scan PDF documents: {err}"))?;
+    let pdfium = Pdfium::new(bindings);
+    let document = pdfium.load_pdf_from_file(path, None)?;
+    let config = PdfRenderConfig::new().set_target_width(PDF_RENDER_WIDTH);
+
+    let pages = document.pages();
+    let count = pages.len() as u32;
+    if let Some(page) = args.page.filter(|&page| page > count) {
+        anyhow::bail!("page {page} is out of range, there are {count} pages");
+    }
+
+    let images = pages
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| args.page.is_none_or(|page| page as usize == i + 1))
+        .map(|(i, page)| {
+            let image = page.render_with_config(&config)?.as_image();
+            Ok((i + 1, image))
+        });
+    scan_pages(args, images)
+}
+
+/// Scan the numbered pages, keeping the ones with codes. Fails with the first error
+/// of a page if none has a code, or right away with --page.
+fn scan_pages(
+    args: &Args,
+    images: impl Iterator<Item = Result<(usize, DynamicImage)>>,
+) -> Result<Vec<Scanned>> {
+    let mut scanned = vec![];
+    let mut error = None;
+    for image in images {
+        let (page, image) = image?;
+        match grid_codes(args, &image) {
+            Ok((codes, failed)) => scanned.push(Scanned {
+                codes,
+                failed,
+                page: Some(page),
+            }),
+            Err(err) if args.page.is_some() => return Err(err),
+            Err(err) => {
+                let decoding = matches!(
+                    err.downcast_ref(),
+                    Some(
+                        ScanError::DecodeFailed(_)
+                            | ScanError::IncompleteSequence { .. }
+                    )
+                );
+                if decoding && error.is_none() {
+                    error = Some(err);
+                }
+            }
+        }
+    }
+
+    if scanned.is_empty() {
+        return Err(error.unwrap_or_else(|| ScanError::NoCodeFound.into()));
+    }
+
+    Ok(scanned)
+}
+
+fn find_images(dir: &Path, images: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.path());
+
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_images(&path, images)?;
+        } else if image::ImageFormat::from_path(&path).is_ok()
+            || is_heif(&path).is_ok_and(|heif| heif)
+            || is_video(&path)
+            || path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            images.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn scan_dir(args: &Args, dir: &Path) -> i32 {
+    let mut images = vec![];
+    if let Err(err) = find_images(dir, &mut images) {
+        eprintln!("error: qrscan: {}: {err}", dir.display());
+        return 1;
+    }
+
+    // Exit with the first error code, like with multiple paths
+    let mut rc = 0;
+    let mut failed = 0;
+    let progress = Progress::new(args, images.len());
+    let decoded = decode_files(args, &images, &progress);
+    for (path, decoded) in images.iter().zip(decoded) {
+        let decoded = decoded.unwrap_or_else(|| decode_file(args, path));
+        progress.clear();
+        let result = decoded.and_then(|scanned| print_scanned(args, path, scanned));
+        if let Err(err) = &result {
+            eprintln!("error: qrscan: {}: {err}", path.display());
+            failed += 1;
+            if rc == 0 {
+                rc = exit_code(err);
+            }
+        }
+        progress.done(result.is_ok());
+    }
+    progress.clear();
+
+    if !args.quiet {
+        eprintln!(
+            "Scanned {} files in {}: {} succeeded, {failed} failed",
+            images.len(),
+            dir.display(),
+            images.len() - failed,
+        );
+    }
+
+    rc
+}
+
+fn scan_path(args: &Args, path: &Path) -> i32 {
+    if path.to_str() == Some("-") {
+        if let Err(err) = scan_stdin(args, path) {
+            eprintln!("error: qrscan: {err}");
+            return exit_code(&err);
+        }
+    } else if let Some(url) = url_of(path) {
+        if let Err(err) = scan_url(args, url) {
+            eprintln!("error: qrscan: {url}: {err}");
+            return exit_code(&err);
+        }
+    } else if !path.exists() {
+        eprintln!("error: qrscan: {}: No such file", path.display());
+        return 3;
+    } else if path.is_dir() && args.recursive {
+        return scan_dir(args, path);
+    } else if path.is_dir() {
+        eprintln!(
+            "error: qrscan: cannot scan {}: Is a directory",
+            path.display()
+        );
+        return 2;
+    } else {
+        return print_decoded(args, path, decode_file(args, path));
+    }
+
+    0
+}
+
+/// Print the codes found in the file, or the error. Returns the exit code.
+fn print_decoded(args: &Args, path: &Path, decoded: Result<Vec<Scanned>>) -> i32 {
+    match decoded.and_then(|scanned| print_scanned(args, path, scanned)) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: qrscan: {}: {err}", path.display());
+            exit_code(&err)
+        }
+    }
+}
+
+fn encode(args: &Args, text: &str) -> Result<()> {
+    let content = if text == "-" {
+        read_stdin(args)?
+    } else {
+        text.as_bytes().to_vec()
+    };
+
+    if !args.split {
+        if args.verify {
+            verify(args, &content, None)?;
+        }
+        if args.matrix {
+            print_generated_matrix(args, &content, None)?;
+        } else if args.qr || !exporting(args) {
+            print_qr(args, &content, None)?;
+        }
+        return export_code(args, &content, None, None);
+    }
+
+    let opts = render_options(args)?;
+    for (i, (chunk, part)) in qrscan::split_content(&content, &opts)?
+        .into_iter()
+        .enumerate()
+    {
+        if args.verify {
+            verify(args, chunk, Some(part))?;
+        }
+        if args.matrix {
+            if i != 0 && !args.json {
+                println!();
+            }
+            print_generated_matrix(args, chunk, Some(part))?;
+        } else if args.qr || !exporting(args) {
+            if i != 0 {
+                println!();
+            }
+            print_qr(args, chunk, Some(part))?;
+        }
+        export_code(args, chunk, None, Some(part))?;
+    }
+
+    Ok(())
+}
+
+/// Print and export the QR codes found in the image. The source is the path of the
+/// input image, if any.
+fn print_image(args: &Args, image: &DynamicImage, source: Option<&Path>) -> Result<()> {
+    let (codes, failed) = grid_codes(args, image)?;
+    print_codes(args, &codes, &failed, source, None)
+}
+
+/// The QR codes found in the image, and with --include-failed, the detected ones that
+/// failed to decode. Fails only if none of them was detected.
+fn grid_codes(
+    args: &Args,
+    image: &DynamicImage,
+) -> Result<(Vec<DecodedCode>, Vec<FailedGrid>)> {
+    match scan_image_failed(args, image) {
+        Err(err)
+            if args.count
+                && matches!(err.downcast_ref(), Some(ScanError::NoCodeFound)) =>
+        {
+            Ok((vec![], vec![]))
+        }
+        result => result,
+    }
+}
+
+/// Why the QR code failed to decode, without the "failed to decode" wording.
+fn decode_error(err: &ScanError) -> String {
+    match err {
+        ScanError::DecodeFailed(err) => err.to_string(),
+        err => err.to_string(),
+    }
+}
+
+/// Scan the image for the first QR code, or all of them with --all or --count.
+fn scan_image(args: &Args, image: &DynamicImage) -> Result<Vec<DecodedCode>> {
+    let (codes, failed) = scan_image_failed(args, image)?;
+    match failed.into_iter().next() {
+        Some(failed) if codes.is_empty() => Err(failed.error.into()),
+        _ => Ok(codes),
+    }
+}
+
+/// Same as [`scan_image`], also returning the detected QR codes that failed to
+/// decode with --include-failed. Then if none of them could be decoded, the failed
+/// ones are returned instead of the error.
+fn scan_image_failed(
+    args: &Args,
+    image: &DynamicImage,
+) -> Result<(Vec<DecodedCode>, Vec<FailedGrid>)> {
+    let opts = scan_options(args);
+
+    let (width, height) = (image.width(), image.height());
+    debug(args, 1, format_args!("scanning {width}x{height} image"));
+    debug(
+        args,
+        2,
+        format_args!("preprocessing: {}", preprocessing(args)),
+    );
+
+    let crop = args
+        .crop
+        .map(|crop| clamp_crop(crop, width, height))
+        .transpose()?;
+    let cropped =
+        crop.map(|crop| image.crop_imm(crop.x, crop.y, crop.width, crop.height));
+    let image = cropped.as_ref().unwrap_or(image);
+
+    if args.verbose >= 2 {
+        let start = Instant::now();
+        let grids = qrscan::count_grids(image, &opts);
+        let elapsed = start.elapsed();
+        debug(
+            args,
+            2,
+            format_args!("detected {grids} QR codes in {elapsed:?}"),
+        );
+    }
+
+    let start = Instant::now();
+    let result = qrscan::scan_image_failed(image, &opts);
+    let elapsed = start.elapsed();
+    let (mut codes, mut failed) = match result {
+        Ok(scanned) => scanned,
+        Err(err) => {
+            debug(args, 1, format_args!("failed in {elapsed:?}: {err}"));
+            return Err(err.into());
+        }
+    };
+    match failed.first() {
+        Some(failed) if codes.is_empty() => debug(
+            args,
+            1,
+            format_args!("failed in {elapsed:?}: {}", failed.error),
+        ),
+        _ => debug(
+            args,
+            1,
+            format_args!("decoded {} QR codes in {elapsed:?}", codes.len()),
+        ),
+    }
+
+    // Report the positions of the failed codes in the whole image
+    if let Some(crop) = crop {
+        let (dx, dy) = (crop.x as i32, crop.y as i32);
+        for failed in failed.iter_mut() {
+            failed.bounds = failed.bounds.map(|(x, y)| (x + dx, y + dy));
+        }
+    }
+
+    // Every code detected failed to decode
+    if codes.is_empty() && !failed.is_empty() {
+        if args.include_failed {
+            return Ok((codes, failed));
+        }
+
+        // The structure of a damaged code may still be readable
+        let err = failed.remove(0).error;
+        if args.metadata_only {
+            codes = qrscan::read_metadata(image, &opts);
+        }
+        if codes.is_empty() {
+            return Err(err.into());
+        }
+        clear_progress(args);
+        eprintln!(
+            "warning: failed to decode the content: {}",
+            decode_error(&err)
+        );
+    }
+
+    if !args.include_failed {
+        failed.clear();
+    }
+
+    // Only the codes printed are checked, or all the parts of those joined
+    let printed = if args.all || args.count || args.join {
+        codes.len()
+    } else {
+        1
+    };
+
+    if args.strict_encoding {
+        if let Some(code) = codes
+            .iter()
+            .take(printed)
+            .find(|code| code.text().is_none())
+        {
+            let encoding = code.encoding.map_or("UTF-8", |encoding| encoding.name());
+            let err = format!("the content is not valid {encoding}");
+            return Err(ScanError::DecodeFailed(err.into()).into());
+        }
+    }
+
+    if args.strict {
+        for code in codes
+            .iter()
+            .take(printed)
+            .filter(|code| code.symbology == Symbology::Qr)
+        {
+            let err = match code.corrections {
+                Some(corrections) if !corrections.is_damaged() => continue,
+                Some(corrections) => format!(
+                    "{} of the {} correctable errors of a block were corrected, the QR code may be damaged",
+                    corrections.block_errors, corrections.block_max_errors
+                ),
+                None => "could not count the corrected errors".into(),
+            };
+            return Err(ScanError::DecodeFailed(err.into()).into());
+        }
+    }
+
+    if args.join {
+        codes = qrscan::join_parts(codes)?;
+    }
+
+    // Report the positions in the whole image
+    if let Some(crop) = crop {
+        let (dx, dy) = (crop.x as i32, crop.y as i32);
+        for code in codes.iter_mut() {
+            code.bounds = code.bounds.map(|(x, y)| (x + dx, y + dy));
+        }
+    }
+
+    if !args.all && !args.count {
+        codes.truncate(1);
+    }
+
+    if codes.is_empty() {
+        return Err(ScanError::NoCodeFound.into());
+    };
+
+    Ok((codes, failed))
+}
+
+fn scan_options(args: &Args) -> ScanOptions {
+    let threshold = match (args.threshold, args.adaptive_threshold) {
+        (Some(threshold), _) => Some(Threshold::Fixed(threshold)),
+        (None, true) => Some(Threshold::Adaptive),
+        (None, false) => None,
+    };
+
+    ScanOptions {
+        rotate: !args.no_rotate,
+        threshold,
+        invert: args.invert_input,
+        min_size: args.min_size,
+        max_dimension: args.max_dimension,
+        symbology: args.symbology.map(Into::into),
+        min_modules: args.min_modules as usize,
+        max_modules: args.max_modules as usize,
+    }
+}
+
+/// Profile scanning the file, or stdin, or print the error. Returns the exit code.
+fn profile_path(args: &Args, path: &Path) -> i32 {
+    if path.to_str() != Some("-") && !path.exists() {
+        eprintln!("error: qrscan: {}: No such file", path.display());
+        return 3;
+    }
+
+    match profile(args, path) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: qrscan: {}: {err}", path.display());
+            exit_code(&err)
+        }
+    }
+}
+
+/// Print how long reading and scanning the image takes, stage by stage.
+fn profile(args: &Args, path: &Path) -> Result<()> {
+    let mut stages = vec![];
+    let mut time =
+        |stage: &'static str, start: Instant| stages.push((stage, start.elapsed()));
+
+    let start = Instant::now();
+    let buf = if path.to_str() == Some("-") {
+        read_stdin(args)?
+    } else {
+        std::fs::read(path)?
+    };
+    time("read", start);
+
+    let start = Instant::now();
+    let image = decode_buffer(args, buf)?;
+    time("image decode", start);
+
+    let start = Instant::now();
+    let (width, height) = (image.width(), image.height());
+    let crop = args
+        .crop
+        .map(|crop| clamp_crop(crop, width, height))
+        .transpose()?;
+    let image = match crop {
+        Some(crop) => image.crop_imm(crop.x, crop.y, crop.width, crop.height),
+        None => image,
+    };
+    time("crop", start);
+
+    let profile = qrscan::profile_scan(&image, &scan_options(args));
+    stages.extend([
+        ("resize", profile.resize),
+        ("luma", profile.luma),
+        ("threshold", profile.threshold),
+        ("prepare", profile.prepare),
+        ("detect grids", profile.detect),
+        ("decode grids", profile.decode),
+    ]);
+
+    clear_progress(args);
+    if args.image.len() > 1 {
+        print_label(Some(path), None);
+    }
+    println!("{:<14}{width}x{height}", "image");
+    for (stage, elapsed) in stages.iter() {
+        println!("{stage:<14}{elapsed:.1?}");
+    }
+    let total: Duration = stages.iter().map(|(_, elapsed)| *elapsed).sum();
+    println!("{:<14}{total:.1?}", "total");
+    println!(
+        "{:<14}{} detected, {} decoded",
+        "QR codes", profile.grids, profile.decoded
+    );
+
+    Ok(())
+}
+
+/// Print the diagnostic message if the verbosity is at least the given level.
+fn debug(args: &Args, level: u8, message: std::fmt::Arguments) {
+    if args.verbose >= level {
+        clear_progress(args);
+        eprintln!("debug: {message}");
+    }
+}
+
+/// The preprocessing steps to run before and while scanning.
+fn preprocessing(args: &Args) -> String {
+    let mut steps = vec![];
+    if let Some(crop) = args.crop {
+        let Crop {
+            x,
+            y,
+            width,
+            height,
+        } = crop;
+        steps.push(format!("crop to {x},{y},{width},{height}"));
+    }
+    if let Some(min_size) = args.min_size {
+        steps.push(format!("upscale to {min_size} pixels"));
+    }
+    if let Some(max_dimension) = args.max_dimension {
+        steps.push(format!("downscale to {max_dimension} pixels"));
+    }
+    if args.invert_input {
+        steps.push("invert".into());
+    }
+    if let Some(threshold) = args.threshold {
+        steps.push(format!("threshold at {threshold}"));
+    }
+    if args.adaptive_threshold {
+        steps.push("adaptive threshold".into());
+    }
+    if !args.no_rotate {
+        steps.push("rotate if needed".into());
+    }
+
+    if steps.is_empty() {
+        "none".into()
+    } else {
+        steps.join(", ")
+    }
+}
+
+/// Fit the crop region into the image, with a warning if it goes out of bounds.
+fn clamp_crop(crop: Crop, width: u32, height: u32) -> Result<Crop> {
+    let x = crop.x.min(width);
+    let y = crop.y.min(height);
+    let clamped = Crop {
+        x,
+        y,
+        width: crop.width.min(width - x),
+        height: crop.height.min(height - y),
+    };
+
+    if clamped.width == 0 || clamped.height == 0 {
+        anyhow::bail!("crop region is outside the {width}x{height} image");
+    }
+
+    if (clamped.width, clamped.height) != (crop.width, crop.height)
+        && !CROP_CLAMPED.swap(true, Ordering::Relaxed)
+    {
+        eprintln!(
+            "warning: crop region clamped to {},{},{},{} to fit the {width}x{height} image",
+            clamped.x, clamped.y, clamped.width, clamped.height
+        );
+    }
+
+    Ok(clamped)
+}
+
+/// Clear the "Scanning via camera..." line.
+fn clear_progress(args: &Args) {
+    if !args.quiet {
+        eprint!("\r                        \r");
+    }
+}
+
+/// Print and export the QR codes. The page is the number of the PDF page they were
+/// found on, if any.
+fn print_codes(
+    args: &Args,
+    codes: &[DecodedCode],
+    failed: &[FailedGrid],
+    source: Option<&Path>,
+    page: Option<usize>,
+) -> Result<()> {
+    clear_progress(args);
+
+    // On stderr, to keep the output clean
+    if args.beep && !codes.is_empty() {
+        eprint!("\x07");
+    }
+
+    // Label the results when scanning multiple files or the pages of a document
+    let label =
+        source.filter(|_| args.image.len() > 1 || args.recursive || page.is_some());
+
+    if args.count {
+        print_label(label, page);
+        println!("{}", codes.len());
+
+        return match args.expect {
+            Some(expected) if codes.len() != expected => Err(UnexpectedCount {
+                expected,
+                found: codes.len(),
+            }
+            .into()),
+            None if codes.is_empty() => Err(ScanError::NoCodeFound.into()),
+            _ => Ok(()),
+        };
+    }
+
+    let timestamp = args.timestamps.then(|| timestamp(args));
+
+    if args.json {
+        let mut json: Vec<_> = codes
+            .iter()
+            .map(|code| {
+                let mut json = JsonCode::new(code, label, page, timestamp.as_deref());
+                json.decoded = args.include_failed.then_some(true);
+                json.matrix = args.matrix.then(|| code.modules.as_slice().into());
+                JsonGrid::Decoded(Box::new(json))
+            })
+            .collect();
+        json.extend(failed.iter().map(|failed| {
+            JsonGrid::Failed(JsonFailedGrid {
+                path: label,
+                page,
+                timestamp: timestamp.as_deref(),
+                decoded: false,
+                error: decode_error(&failed.error),
+                bounds: failed.bounds,
+            })
+        }));
+
+        if args.all {
+            println!("{}", serde_json::to_string(&json)?);
+        } else {
+            println!("{}", serde_json::to_string(&json[0])?);
+        }
+
+        // Listed, but still an error if none of the codes decoded
+        if let (true, Some(failed)) = (codes.is_empty(), failed.first()) {
+            let err = decode_error(&failed.error);
+            return Err(ScanError::DecodeFailed(err.into()).into());
+        }
+    } else {
+        print_label(label, page);
+
+        for (i, code) in codes.iter().enumerate() {
+            if i != 0
+                && (args.qr
+                    || args.matrix
+                    || args.metadata
+                    || args.metadata_only
+                    || (!args.no_content && terminator(args) == "\n"))
+            {
+                println!();
+            }
+            if let Some(timestamp) = timestamp.as_ref() {
+                print!("{timestamp} ");
+            }
+            print_code(args, code)?;
+        }
+    }
+
+    if args.notify {
+        for code in codes {
+            notify(code);
+        }
+    }
+
+    if let Some(path) = args.output.as_ref() {
+        write_contents(args, path, source, codes)?;
+    }
+
+    if let Some(code) = codes.first() {
+        if let Some(path) = args.raw.as_ref() {
+            write_output(args, path, source, &code.content)?;
+        }
+
+        if args.clipboard {
+            copy_to_clipboard(code.text_lossy().as_bytes())?;
+
+            if codes.len() > 1 {
+                eprintln!("note: copied only the first of {} codes", codes.len());
+            }
+        }
+
+        if args.open {
+            open_url(code)?;
+        }
+
+        if args.verify && exporting(args) {
+            verify(args, &code.content, None)?;
+        }
+        export_code(args, &code.content, source, None)?;
+    }
+
+    Ok(())
+}
+
+/// The current time in ISO-8601 format, in UTC with --utc.
+fn timestamp(args: &Args) -> String {
+    if args.utc {
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    } else {
+        chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+    }
+}
+
+/// Print the path the following results were found in, if any.
+fn print_label(label: Option<&Path>, page: Option<usize>) {
+    match (label, page) {
+        (Some(path), Some(page)) => println!("{} (page {page}):", path.display()),
+        (Some(path), None) => println!("{}:", path.display()),
+        (None, _) => {}
+    }
+}
+
+/// Send a desktop notification with the content, with a warning if there is no
+/// notification service.
+fn notify(code: &DecodedCode) {
+    let result = notify_rust::Notification::new()
+        .summary("QR code scanned")
+        .body(&notification_body(code.text_lossy().as_bytes()))
+        .show();
+
+    if let Err(err) = result {
+        if !NOTIFY_FAILED.swap(true, Ordering::Relaxed) {
+            eprintln!("warning: cannot send the notification: {err}");
+        }
+    }
+}
+
+/// The content as text, truncated to [`NOTIFY_MAX_CHARS`] characters.
+fn notification_body(content: &[u8]) -> String {
+    let content = String::from_utf8_lossy(content);
+    let mut body: String = content.chars().take(NOTIFY_MAX_CHARS).collect();
+    if body.len() < content.len() {
+        body.push('…');
+    }
+    body
+}
+
+/// Open the content with the system opener, unless it is not a plain http(s) URL.
+fn open_url(code: &DecodedCode) -> Result<()> {
+    let text = code.text();
+    let Some(url) = text.as_deref().filter(|url| is_openable_url(url)) else {
+        eprintln!("warning: not opening the content as it is not an http or https URL");
+        return Ok(());
+    };
+
+    if OPENED.lock().unwrap().insert(url.to_string()) {
+        open::that(url)?;
+    }
+
+    Ok(())
+}
+
+/// Whether the text is a single http(s) URL, without any whitespace or control
+/// characters that the system opener could misinterpret.
+fn is_openable_url(text: &str) -> bool {
+    qrscan::classify(text) == qrscan::ContentType::Url
+        && !text.starts_with(['-', ' '])
+        && !text.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+fn copy_to_clipboard(content: &[u8]) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new()?;
+    clipboard.set_text(String::from_utf8_lossy(content))?;
+    Ok(())
+}
+
+fn print_qr(args: &Args, content: &[u8], part: Option<StructuredAppend>) -> Result<()> {
+    let mut opts = render_options(args)?;
+    opts.structured_append = part;
+    let style = match args.qr_style {
+        QrStyle::Auto if use_color(args) && utf8_locale() => QrStyle::Unicode,
+        QrStyle::Auto => QrStyle::Ascii,
+        style => style,
+    };
+
+    let image = match style {
+        QrStyle::Braille => {
+            qrscan::encode_to_braille(content, &opts, args.invert_colors)?
+        }
+        QrStyle::Ascii => qrscan::encode_to_ascii(content, &opts)?,
+        _ => qrscan::encode_to_unicode(content, &opts, args.invert_colors)?,
+    };
+    println!("{image}");
+    Ok(())
+}
+
+/// Print the modules as the width and height, then rows of 0 and 1 for the dark ones.
+fn print_matrix(modules: &[Vec<bool>]) {
+    let width = modules.first().map_or(0, Vec::len);
+    println!("{width}x{}", modules.len());
+    for row in modules {
+        let row: String = row
+            .iter()
+            .map(|dark| if *dark { '1' } else { '0' })
+            .collect();
+        println!("{row}");
+    }
+}
+
+/// Print the modules of the QR code of the content, as a JSON line with --json.
+fn print_generated_matrix(
+    args: &Args,
+    content: &[u8],
+    part: Option<StructuredAppend>,
+) -> Result<()> {
+    let mut opts = render_options(args)?;
+    opts.structured_append = part;
+    let modules = qrscan::encode_to_matrix(content, &opts)?;
+
+    if args.json {
+        let json = JsonMatrix::from(modules.as_slice());
+        println!("{}", serde_json::to_string(&json)?);
+    } else {
+        print_matrix(&modules);
+    }
+    Ok(())
+}
+
+/// Whether the locale uses UTF-8, so that the terminal likely has the block glyphs.
+fn utf8_locale() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .find(|value| !value.is_empty());
+
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_string_lossy().to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        // Windows terminals do not set it
+        None => cfg!(windows),
+    }
+}
+
+/// Whether to print colors and blocks, unless disabled or stdout is not a capable
+/// terminal.
+fn use_color(args: &Args) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+    !args.no_color && !no_color && !dumb && std::io::stdout().is_terminal()
+}
+
+fn print_code(args: &Args, code: &DecodedCode) -> Result<()> {
+    // Ansi
+    if args.qr {
+        if args.preview {
+            println!();
+        }
+        print_qr(args, &code.content, None)?;
+    }
+
+    // Matrix
+    if args.matrix {
+        if args.preview || args.qr {
+            println!();
+        }
+        print_matrix(&code.modules);
+    }
+
+    // Metadata
+    if args.metadata || args.metadata_only {
+        if args.preview || args.qr || args.matrix {
+            println!()
+        };
+
+        if code.symbology == Symbology::Qr {
+            println!("Version: {}", code.version);
+            println!("Grid Size: {}", code.grid_size());
+            println!("EC Level: {}", code.ecc_level);
+            println!("Mask: {}", code.mask);
+            if let Some(capacity) = code.capacity() {
+                println!("Capacity Bytes: {capacity}");
+            }
+            if let Some(corrections) = code.corrections {
+                let (errors, max_errors) = (corrections.errors, corrections.max_errors);
+                println!("Errors Corrected: {errors} of {max_errors}");
+            }
+            if let Some(encoding) = code.encoding {
+                println!("Encoding: {}", encoding.name());
+            }
+            if let Some(part) = code.structured_append {
+                println!("Part: {} of {}", part.index + 1, part.total);
+            }
+        } else {
+            println!("Symbology: {}", code.symbology.as_str());
+        }
+        let bounds: Vec<_> = code
+            .bounds
+            .iter()
+            .map(|(x, y)| format!("{x},{y}"))
+            .collect();
+        println!("Bounds: {}", bounds.join(" "));
+        if !args.metadata_only {
+            println!("Content Bytes: {}", code.content.len());
+            println!("Type: {}", content_type(code));
+        }
+    }
+
+    // Content
+    let to_stdout = [args.raw.as_ref(), args.output.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|path| path.to_str() == Some("-"));
+    if !args.no_content && !args.metadata_only && !to_stdout {
+        if args.preview || args.qr || args.matrix || args.metadata {
+            println!();
+        };
+
+        let mut text = code.text_lossy();
+        if args.url_decode {
+            if let Some(decoded) = qrscan::url_decode(&text) {
+                text = Cow::Owned(decoded.into_owned());
+            }
+        }
+        let wifi = Some(text.as_ref())
+            .filter(|_| args.parse)
+            .and_then(qrscan::parse_wifi);
+
+        if let Some(wifi) = wifi {
+            print_wifi(&wifi);
+        } else {
+            if text.is_empty() {
+                // On stderr, so that the empty line still stands for the code
+                eprintln!("(empty content)");
+            }
+            let mut stdout = std::io::stdout();
+            write!(stdout, "{text}{}", terminator(args))?;
+            stdout.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// What the printed contents end with, a newline unless with --no-newline or --null.
+fn terminator(args: &Args) -> &'static str {
+    if args.no_newline {
+        ""
+    } else if args.null {
+        "\0"
+    } else {
+        "\n"
+    }
+}
+
+/// The type of the content, or "binary" if it is not valid in its encoding.
+fn content_type(code: &DecodedCode) -> &'static str {
+    code.text()
+        .map(|content| qrscan::classify(&content).as_str())
+        .unwrap_or("binary")
+}
+
+fn print_wifi(wifi: &WifiNetwork) {
+    println!("SSID: {}", wifi.ssid);
+    println!("Authentication: {}", wifi.auth);
+    if let Some(password) = &wifi.password {
+        println!("Password: {password}");
+    }
+    println!("Hidden: {}", wifi.hidden);
+}
+
+/// Replace "{name}" in the output path with the source file name.
+fn output_path(path: &Path, source: Option<&Path>) -> PathBuf {
+    match path.to_str() {
+        Some(template) if template.contains("{name}") => {
+            let name = source
+                .filter(|source| source.to_str() != Some("-"))
+                .and_then(Path::file_stem)
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_else(|| "qrcode".into());
+            template.replace("{name}", &name).into()
+        }
+        _ => path.into(),
+    }
+}
+
+/// Write the bytes to the file, or stdout with "-". Existing files are overwritten,
+/// unless with --no-clobber.
+fn write_output(
+    args: &Args,
+    path: &Path,
+    source: Option<&Path>,
+    bytes: &[u8],
+) -> Result<()> {
+    if path.to_str() == Some("-") {
+        std::io::stdout().write_all(bytes)?;
+        return Ok(());
+    }
+
+    let path = output_path(path, source);
+    if args.no_clobber {
+        let mut file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::AlreadyExists => anyhow::anyhow!(
+                    "{}: already exists, not overwriting it with --no-clobber",
+                    path.display()
+                ),
+                _ => err.into(),
+            })?;
+        file.write_all(bytes)?;
+    } else {
+        std::fs::write(path, bytes)?;
+    }
+    Ok(())
+}
+
+/// Write the exported file, or add it to the archive with --bundle. Adding the same
+/// name again replaces the file, unless with --no-clobber.
+fn export_output(
+    args: &Args,
+    path: &Path,
+    source: Option<&Path>,
+    bytes: &[u8],
+) -> Result<()> {
+    if args.bundle.is_none() {
+        return write_output(args, path, source, bytes);
+    }
+
+    let path = output_path(path, source);
+    let name = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if name.is_empty() || name == "-" {
+        anyhow::bail!(
+            "{}: not a file name to write into the bundle",
+            path.display()
+        );
+    }
+
+    let mut bundle = BUNDLE.lock().unwrap();
+    match bundle.iter_mut().find(|(bundled, _)| *bundled == name) {
+        Some(_) if args.no_clobber => {
+            anyhow::bail!(
+                "{name}: already in the bundle, not replacing it with --no-clobber"
+            )
+        }
+        Some((_, bundled)) => *bundled = bytes.to_vec(),
+        None => bundle.push((name, bytes.to_vec())),
+    }
+    Ok(())
+}
+
+/// Write the files exported so far into the --bundle archive.
+fn write_bundle(args: &Args, path: &Path) -> Result<()> {
+    let bundle = BUNDLE.lock().unwrap();
+    if bundle.is_empty() {
+        return Ok(());
+    }
+    write_output(args, path, None, &archive::zip_files(&bundle)?)
+}
+
+/// Write the content of the codes as text, one per line, or each to its own file if
+/// the path has "{index}".
+fn write_contents(
+    args: &Args,
+    path: &Path,
+    source: Option<&Path>,
+    codes: &[DecodedCode],
+) -> Result<()> {
+    match path.to_str() {
+        Some(template) if template.contains("{index}") => {
+            for (i, code) in codes.iter().enumerate() {
+                let path =
+                    PathBuf::from(template.replace("{index}", &(i + 1).to_string()));
+                write_output(args, &path, source, code.text_lossy().as_bytes())?;
+            }
+        }
+        _ => {
+            let mut text = String::new();
+            for code in codes {
+                text.push_str(&code.text_lossy());
+                text.push_str(terminator(args));
+            }
+            write_output(args, path, source, text.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the QR code as for the raster images, and check that it scans back to the
+/// content.
+fn verify(args: &Args, content: &[u8], part: Option<StructuredAppend>) -> Result<()> {
+    let mut opts = render_options(args)?;
+    opts.structured_append = part;
+    let image = DynamicImage::ImageRgba8(qrscan::build_binary_image(content, &opts)?);
+
+    let codes = qrscan::scan_image(&image)
+        .map_err(|err| anyhow::anyhow!("verification failed: {err}"))?;
+    match codes.first() {
+        Some(code) if code.content == content && code.structured_append == part => {
+            debug(args, 1, format_args!("verified the generated QR code"));
+            Ok(())
+        }
+        Some(_) => {
+            anyhow::bail!("verification failed: the QR code decodes to another content")
+        }
+        None => anyhow::bail!("verification failed: the QR code cannot be scanned back"),
+    }
+}
+
+/// Replace "{index}" in the export path with the number of the part, which it must
+/// contain.
+fn part_path(path: &Path, part: Option<StructuredAppend>) -> Result<Cow<'_, Path>> {
+    let Some(part) = part else {
+        return Ok(path.into());
+    };
+
+    match path.to_str() {
+        Some(template) if template.contains("{index}") => {
+            let index = (part.index + 1).to_string();
+            Ok(PathBuf::from(template.replace("{index}", &index)).into())
+        }
+        _ => anyhow::bail!(
+            "the path {} must contain \"{{index}}\" to split the content",
+            path.display()
+        ),
+    }
+}
+
+fn render_options(args: &Args) -> Result<RenderOptions> {
+    // Output image colors
+    let (dark, light) = if args.invert_colors {
+        (&args.bg, &args.fg)
+    } else {
+        (&args.fg, &args.bg)
+    };
+
+    let light = light.parse::<Color>()?;
+
+    if let Some(gradient) = args.fg_gradient.as_ref() {
+        for color in [&gradient.start, &gradient.end] {
+            if contrast(color, &light) < MIN_CONTRAST {
+                anyhow::bail!(
+                    "the gradient color {} is too close to the background color {} to scan",
+                    color.to_hex_string(),
+                    light.to_hex_string()
+                );
+            }
+        }
+    }
+
+    Ok(RenderOptions {
+        dark: dark.parse::<Color>()?,
+        gradient: args.fg_gradient.clone(),
+        light,
+        color_space: args.color_space.into(),
+        quiet_zone: if args.no_quiet_zone {
+            0
+        } else {
+            args.quiet_zone_size
+        },
+        module_size: args.module_size,
+        size: args.size,
+        dpi: args.dpi,
+        jpeg_quality: args.jpeg_quality,
+        ec_level: args.ec_level.into(),
+        min_version: args.min_version,
+        max_version: args.max_version,
+        mask: args.mask,
+        micro: args.micro,
+        structured_append: None,
+        style: args.style.into(),
+        logo: logo(args)?,
+        logo_size: args.logo_size,
+    })
+}
+
+/// The image of --logo, loaded on first use and kept for the other codes rendered.
+/// Only the exported and verified images have the logo.
+fn logo(args: &Args) -> Result<Option<DynamicImage>> {
+    let Some(path) = args
+        .logo
+        .as_ref()
+        .filter(|_| exporting(args) || args.verify)
+    else {
+        return Ok(None);
+    };
+    if let Some(logo) = LOGO.get() {
+        return Ok(Some(logo.clone()));
+    }
+
+    let logo =
+        image::open(path).map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+    Ok(Some(LOGO.get_or_init(|| logo).clone()))
+}
+
+/// The contrast ratio between the colors over a white background, from 1 to 21.
+fn contrast(a: &Color, b: &Color) -> f64 {
+    let luminance = |color: &Color| {
+        let (r, g, b, alpha) = color.to_linear_rgba();
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        luminance * alpha + 1.0 - alpha
+    };
+
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn exporting(args: &Args) -> bool {
+    args.svg.is_some()
+        || args.ascii.is_some()
+        || args.png.is_some()
+        || args.jpeg.is_some()
+        || args.webp.is_some()
+        || args.gif.is_some()
+        || args.bmp.is_some()
+        || args.tiff.is_some()
+        || args.pdf.is_some()
+        || args.eps.is_some()
+}
+
+/// Export the QR code to the given paths. The part of a split content gets exported
+/// to the paths with its number.
+fn export_code(
+    args: &Args,
+    content: &[u8],
+    source: Option<&Path>,
+    part: Option<StructuredAppend>,
+) -> Result<()> {
+    let mut opts = render_options(args)?;
+    opts.structured_append = part;
+
+    // SVG
+    if let Some(path) = args.svg.as_ref() {
+        let image = qrscan::encode_to_svg(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, image.as_bytes())?;
+    }
+
+    // Ascii
+    if let Some(path) = args.ascii.as_ref() {
+        let image = qrscan::encode_to_ascii(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, image.as_bytes())?;
+    }
+
+    // PNG
+    if let Some(path) = args.png.as_ref() {
+        let image = qrscan::encode_to_png(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // JPEG
+    if let Some(path) = args.jpeg.as_ref() {
+        if opts.light.a < 1.0 || opts.dark.a < 1.0 {
+            eprintln!(
+                "warning: JPEG has no transparency, exporting on a white background"
+            );
+        }
+        if opts.jpeg_quality < MIN_JPEG_QUALITY {
+            eprintln!(
+                "warning: JPEG quality {} may blur the QR code until it is unscannable",
+                opts.jpeg_quality
+            );
+        }
+        let image = qrscan::encode_to_jpeg(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // WebP
+    if let Some(path) = args.webp.as_ref() {
+        let image = qrscan::encode_to_webp(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // GIF
+    if let Some(path) = args.gif.as_ref() {
+        let image = qrscan::encode_to_gif(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // BMP
+    if let Some(path) = args.bmp.as_ref() {
+        let image = qrscan::encode_to_bmp(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // TIFF
+    if let Some(path) = args.tiff.as_ref() {
+        let image = qrscan::encode_to_tiff(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // PDF
+    if let Some(path) = args.pdf.as_ref() {
+        let image = qrscan::encode_to_pdf(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, &image)?;
+    }
+
+    // EPS
+    if let Some(path) = args.eps.as_ref() {
+        let image = qrscan::encode_to_eps(content, &opts)?;
+        export_output(args, &part_path(path, part)?, source, image.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// The config file in the user's config directory.
+fn default_config_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| Path::new(&home).join(".config"))
+        })
+        .map(|dir| dir.join("qrscan").join("config.toml"))
+}
+
+/// Whether the environment variable is set to a true value, like clap's flags.
+fn env_flag(var: &str) -> bool {
+    std::env::var(var).is_ok_and(|value| {
+        !matches!(
+            value.to_lowercase().as_str(),
+            "" | "0" | "false" | "no" | "off" | "n" | "f"
+        )
+    })
+}
+
+/// The options given on the command line, and the options conflicting with them,
+/// which the environment and the config file must not set, so that the command
+/// line wins instead of clap rejecting the combination.
+fn overridden(cmd: &clap::Command, cli: &[OsString]) -> HashSet<clap::Id> {
+    let Ok(matches) = cmd.clone().ignore_errors(true).try_get_matches_from(cli) else {
+        return HashSet::new();
+    };
+    let given: HashSet<_> = matches
+        .ids()
+        .filter(|id| {
+            matches.value_source(id.as_str())
+                == Some(clap::parser::ValueSource::CommandLine)
+        })
+        .cloned()
+        .collect();
+
+    let mut overridden = given.clone();
+    for arg in cmd.get_arguments() {
+        for other in cmd.get_arg_conflicts_with(arg) {
+            if given.contains(arg.get_id()) {
+                overridden.insert(other.get_id().clone());
+            }
+            if given.contains(other.get_id()) {
+                overridden.insert(arg.get_id().clone());
+            }
+        }
+    }
+    overridden
+}
+
+/// The options in the config file, as command line arguments. The options set in
+/// the environment or overridden on the command line are left out, as they take
+/// precedence.
+fn config_args(config: &str, overridden: &HashSet<clap::Id>) -> Result<Vec<String>> {
+    let table: toml::Table = config.parse()?;
+    let cmd = Args::command();
+
+    let mut args = vec![];
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(&name))
+            .filter(|_| !matches!(name.as_str(), "config" | "no-config"));
+        let Some(arg) = arg else {
+            anyhow::bail!("unknown option: {key}");
+        };
+        let in_env = arg
+            .get_env()
+            .is_some_and(|var| std::env::var_os(var).is_some());
+        if in_env || overridden.contains(arg.get_id()) {
+            continue;
+        }
+
+        match value {
+            toml::Value::Boolean(true) => args.push(format!("--{name}")),
+            toml::Value::Boolean(false) => {}
+            toml::Value::String(value) => args.push(format!("--{name}={value}")),
+            // Repeated on the command line, like -vv
+            toml::Value::Integer(count)
+                if matches!(arg.get_action(), clap::ArgAction::Count) =>
+            {
+                let Ok(count) = u8::try_from(count) else {
+                    anyhow::bail!("{key}: expected a count from 0 to 255");
+                };
+                args.extend((0..count).map(|_| format!("--{name}")));
+            }
+            value @ (toml::Value::Integer(_) | toml::Value::Float(_)) => {
+                args.push(format!("--{name}={value}"))
+            }
+            _ => anyhow::bail!("{key}: expected a string, a number or a boolean"),
+        }
+    }
+
+    Ok(args)
+}
+
+/// Parse the command line arguments, overriding the options in the environment, then
+/// in the config file.
+fn parse_args() -> Result<Args> {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+
+    let cmd = Args::command();
+    let overridden = overridden(&cmd, &cli);
+    for arg in cmd.get_arguments() {
+        if let Some(var) = arg.get_env().filter(|_| overridden.contains(arg.get_id())) {
+            std::env::remove_var(var);
+        }
+    }
+
+    // Find the config file before parsing, as the config options go first
+    let mut path = default_config_path().filter(|path| path.exists());
+    if let Some(config) = std::env::var_os("QRSCAN_CONFIG") {
+        path = Some(config.into());
+    }
+    if env_flag("QRSCAN_NO_CONFIG") {
+        path = None;
+    }
+    let mut rest = cli.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg == "--config" {
+            path = rest.next().map(PathBuf::from);
+        } else if let Some(value) =
+            arg.to_str().and_then(|arg| arg.strip_prefix("--config="))
+        {
+            path = Some(value.into());
+        } else if arg == "--no-config" {
+            path = None;
+            break;
+        }
+    }
+
+    let Some(path) = path else {
+        return Ok(migrate(Args::parse_from(cli)));
+    };
+
+    let config = std::fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|config| config_args(&config, &overridden))
+        .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+
+    let args = cli[..1]
+        .iter()
+        .cloned()
+        .chain(config.into_iter().map(OsString::from))
+        .chain(cli[1..].iter().cloned());
+
+    Ok(migrate(Args::parse_from(args)))
+}
+
+/// Reject the combinations of options that clap cannot check.
+fn check_args(args: Args) -> Result<Args> {
+    if args.min_modules > args.max_modules {
+        anyhow::bail!(
+            "--min-modules {} is above --max-modules {}",
+            args.min_modules,
+            args.max_modules
+        );
+    }
+    if let (Some(min_size), Some(max_dimension)) = (args.min_size, args.max_dimension) {
+        if min_size > max_dimension {
+            anyhow::bail!(
+                "--min-size {min_size} is above --max-dimension {max_dimension}"
+            );
+        }
+    }
+    if args.bundle.is_some() && !exporting(&args) {
+        anyhow::bail!("--bundle needs a format to export, like --png or --svg");
+    }
+    Ok(args)
+}
+
+/// Move the values of the deprecated options to their replacements, with a warning.
+fn migrate(mut args: Args) -> Args {
+    if let Some(interval) = args.inverval.take() {
+        eprintln!("warning: --inverval is deprecated, use --interval instead");
+        args.interval = interval;
+    }
+    args
+}
+
+fn main() {
+    let args = match parse_args().and_then(check_args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: qrscan: {err}");
+            std::process::exit(2);
+        }
+    };
+    let mut rc = 0;
+
+    if args.logo.is_some() && args.logo_size > MAX_SAFE_LOGO_SIZE {
+        eprintln!(
+            "warning: a logo wider than {MAX_SAFE_LOGO_SIZE}% of the QR code may make it unreadable"
+        );
+    }
+
+    if let Some(shell) = args.generate_completions {
+        let mut cmd = Args::command();
+        clap_complete::generate(shell, &mut cmd, "qrscan", &mut std::io::stdout());
+    } else if args.list_cameras {
+        if let Err(err) = list_cameras(&args) {
+            eprintln!("error: qrscan: {err}");
+            rc = 1;
+        }
+    } else if let Some(text) = args.encode.as_ref() {
+        if let Err(err) = encode(&args, text) {
+            eprintln!("error: qrscan: {err}");
+            rc = 1;
+        }
+    } else if args.profile {
+        for (i, path) in args.image.iter().enumerate() {
+            if i != 0 {
+                println!();
+            }
+            let code = profile_path(&args, path);
+            if rc == 0 {
+                rc = code;
+            }
+        }
+    } else if args.stream {
+        match scan_stream(&args) {
+            Ok(code) => rc = code,
+            Err(err) => {
+                eprintln!("error: qrscan: {err}");
+                rc = exit_code(&err);
+            }
+        }
+    } else if !args.image.is_empty() {
+        // Keep going on errors, but exit with the first error code
+        let progress = Progress::new(&args, args.image.len());
+        let decoded = decode_files(&args, &args.image, &progress);
+        for (path, decoded) in args.image.iter().zip(decoded) {
+            progress.clear();
+            let code = match decoded {
+                Some(result) => print_decoded(&args, path, result),
+                None => scan_path(&args, path),
+            };
+            progress.done(code == 0);
+            if rc == 0 {
+                rc = code;
+            }
+        }
+        progress.clear();
+    } else if let Err(err) = capture(&args) {
+        eprintln!("error: qrscan: {err}");
+        rc = exit_code(&err);
+    }
+
+    if let Some(path) = args.bundle.as_ref() {
+        if let Err(err) = write_bundle(&args, path) {
+            eprintln!("error: qrscan: {err}");
+            if rc == 0 {
+                rc = 1;
+            }
+        }
+    }
+
+    std::process::exit(rc);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::Stdio;
+
+    use assert_cmd::prelude::OutputOkExt;
+
+    fn qrscan() -> assert_cmd::Command {
+        let mut cmd = assert_cmd::Command::cargo_bin("qrscan").unwrap();
+
+        // Ignore the config file of the user running the tests
+        cmd.env("XDG_CONFIG_HOME", "test_no_config_home");
+        cmd
+    }
+
+    pub struct TestFile {
+        pub path: PathBuf,
+    }
+
+    impl TestFile {
+        pub fn new(id: &str, ext: &str) -> Self {
+            let path = PathBuf::from(format!("test_{id}.{}", ext));
+            let header = format!("Accept: image/{}", ext);
+            let data = format!("foo {}", ext);
+
+            std::process::Command::new("curl")
+                .arg("https://qrcode.show")
+                .arg("-k")
+                .arg("-H")
+                .arg(&header)
+                .arg("-d")
+                .arg(&data)
+                .arg("-o")
+                .arg(&path)
+                .unwrap();
+
+            Self { path }
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_help() {
+        qrscan().arg("-h").assert().success();
+        qrscan().arg("--help").assert().success();
+    }
+
+    #[test]
+    fn test_generate_completions() {
+        let output = qrscan()
+            .arg("--generate-completions")
+            .arg("bash")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(output.status.success());
+        assert!(stdout.contains("--generate-completions"));
+        assert!(stdout.contains("--encode"));
+
+        qrscan()
+            .arg("--generate-completions")
+            .arg("tcsh")
+            .assert()
+            .failure()
+            .code(2);
+    }
+
+    #[test]
+    fn test_scan_jpeg_file() {
+        let file = TestFile::new("scan_jpeg_file", "jpeg");
+        qrscan()
+            .arg(&file.path)
+            .assert()
+            .success()
+            .stdout("foo jpeg\n");
+    }
+
+    #[test]
+    fn test_scan_png_file() {
+        let file = TestFile::new("scan_png_file", "png");
+        qrscan()
+            .arg(&file.path)
+            .assert()
+            .success()
+            .stdout("foo png\n");
+    }
+
+    #[test]
+    fn test_scan_from_stdin() {
+        let file = TestFile::new("scan_from_stdin", "png");
+        qrscan()
+            .arg("-")
+            .pipe_stdin(&file.path)
+            .unwrap()
+            .assert()
+            .success()
+            .stdout("foo png\n");
+    }
+
+    #[test]
+    fn test_profile() {
+        let png = qrscan::encode_to_png(b"foo profile", &Default::default()).unwrap();
+        std::fs::write("test_profile.png", png).unwrap();
+
+        let output = qrscan()
+            .arg("test_profile.png")
+            .arg("--profile")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let stdout = String::from_utf8(output).unwrap();
+        let stages: Vec<_> = stdout
+            .lines()
+            .map(|line| line.split("  ").next().unwrap())
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                "image",
+                "read",
+                "image decode",
+                "crop",
+                "resize",
+                "luma",
+                "threshold",
+                "prepare",
+                "detect grids",
+                "decode grids",
+                "total",
+                "QR codes"
+            ]
+        );
+        assert!(stdout.starts_with("image         232x232\n"));
+        assert!(stdout.ends_with("QR codes      1 detected, 1 decoded\n"));
+
+        qrscan()
+            .arg("test_profile.png")
+            .arg("test_profile_missing.png")
+            .arg("--profile")
+            .assert()
+            .code(3);
+
+        qrscan().arg("--profile").assert().code(2);
+
+        std::fs::remove_file("test_profile.png").unwrap();
+    }
+
+    #[test]
+    fn test_exif_orientation() {
+        let jpeg = qrscan::encode_to_jpeg(b"foo exif", &Default::default()).unwrap();
+
+        // Stored transposed, with an EXIF segment to transpose it back
+        let image = image::load_from_memory(&jpeg).unwrap().rotate90().fliph();
+        let mut stored = vec![];
+        image
+            .write_to(
+                &mut std::io::Cursor::new(&mut stored),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let mut segment =
+            b"Exif\0\0II\x2a\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        segment.extend([5, 0, 0, 0, 0, 0, 0, 0]);
+        let mut jpeg = stored[..2].to_vec();
+        jpeg.extend([0xff, 0xe1]);
+        jpeg.extend((segment.len() as u16 + 2).to_be_bytes());
+        jpeg.extend(segment);
+        jpeg.extend(&stored[2..]);
+        std::fs::write("test_exif_orientation.jpeg", &jpeg).unwrap();
+
+        qrscan()
+            .arg("test_exif_orientation.jpeg")
+            .assert()
+            .success()
+            .stdout("foo exif\n");
+
+        qrscan()
+            .arg("-")
+            .write_stdin(jpeg)
+            .assert()
+            .success()
+            .stdout("foo exif\n");
+
+        std::fs::remove_file("test_exif_orientation.jpeg").unwrap();
+    }
+
+    #[test]
+    fn test_zip_files() {
+        use std::io::Read;
+
+        let files = vec![
+            ("a.txt".to_string(), b"foo zip".to_vec()),
+            ("dir/b.txt".to_string(), vec![]),
+        ];
+        let zip = super::archive::zip_files(&files).unwrap();
+
+        // Read the files back from the local headers
+        let u16_at = |i: usize| u16::from_le_bytes([zip[i], zip[i + 1]]) as usize;
+        let u32_at = |i: usize| u32::from_le_bytes(zip[i..i + 4].try_into().unwrap());
+        let mut offset = 0;
+        for (name, content) in &files {
+            assert_eq!(u32_at(offset), 0x04034b50);
+            let size = u32_at(offset + 18) as usize;
+            let name_len = u16_at(offset + 26);
+            let start = offset + 30 + name_len;
+            assert_eq!(&zip[offset + 30..start], name.as_bytes());
+
+            let mut decoded = vec![];
+            flate2::read::DeflateDecoder::new(&zip[start..start + size])
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(&decoded, content);
+            assert_eq!(u32_at(offset + 14), crc32fast::hash(content));
+            offset = start + size;
+        }
+
+        // The central directory follows, and the end record counts both files
+        assert_eq!(u32_at(offset), 0x02014b50);
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(end), 0x06054b50);
+        assert_eq!(u16_at(end + 10), 2);
+        assert_eq!(u32_at(end + 16) as usize, offset);
+    }
+
+    /// The JPEG image with an EXIF segment of the given orientation.
+    fn with_orientation(jpeg: &[u8], orientation: u8) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        tiff.extend([0, orientation, 0, 0, 0, 0, 0, 0]);
+
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend(tiff);
+
+        let mut result = jpeg[..2].to_vec();
+        result.extend([0xff, 0xe1]);
+        result.extend((segment.len() as u16 + 2).to_be_bytes());
+        result.extend(segment);
+        result.extend(&jpeg[2..]);
+        result
+    }
+
+    #[test]
+    fn test_apply_orientation() {
+        let jpeg = qrscan::encode_to_jpeg(b"foo exif", &Default::default()).unwrap();
+        assert_eq!(super::exif::exif_orientation(&jpeg), None);
+        assert_eq!(super::exif::exif_orientation(b"not a jpeg"), None);
+
+        // Stored transposed, as mirrored codes cannot be read
+        let image = image::load_from_memory(&jpeg).unwrap().rotate90().fliph();
+        let mut stored = vec![];
+        image
+            .write_to(
+                &mut std::io::Cursor::new(&mut stored),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let stored = with_orientation(&stored, 5);
+        assert_eq!(super::exif::exif_orientation(&stored), Some(5));
+        assert_eq!(super::exif::exif_orientation(&stored[..20]), None);
+
+        let opts = super::ScanOptions {
+            rotate: false,
+            ..Default::default()
+        };
+        let image = image::load_from_memory(&stored).unwrap();
+        assert!(qrscan::scan_image_with(&image, &opts).is_err());
+
+        let image = super::exif::apply_orientation(image, 5);
+        let codes = qrscan::scan_image_with(&image, &opts).unwrap();
+        assert_eq!(codes[0].content, b"foo exif");
+
+        // Each orientation undoes the matching transformation
+        let image = image::load_from_memory(&jpeg).unwrap();
+        let taken = image.crop_imm(0, 0, 50, 30);
+        for (orientation, stored) in [
+            (2, taken.fliph()),
+            (3, taken.rotate180()),
+            (4, taken.flipv()),
+            (5, taken.rotate90().fliph()),
+            (6, taken.rotate270()),
+            (7, taken.rotate90().flipv()),
+            (8, taken.rotate90()),
+        ] {
+            let oriented = super::exif::apply_orientation(stored, orientation);
+            assert_eq!(oriented.to_rgb8(), taken.to_rgb8(), "{orientation}");
+        }
+    }
+
+    #[test]
+    fn test_invert_input() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo inverted")
+            .arg("--invert-colors")
+            .arg("--png")
+            .arg("test_invert_input.png")
+            .assert()
+            .success();
+
+        qrscan().arg("test_invert_input.png").assert().code(5);
+
+        qrscan()
+            .arg("test_invert_input.png")
+            .arg("--invert-input")
+            .assert()
+            .success()
+            .stdout("foo inverted\n");
+
+        std::fs::remove_file("test_invert_input.png").unwrap();
+    }
+
+    #[test]
+    fn test_matrix() {
+        let output = qrscan()
+            .arg("--encode")
+            .arg("foo matrix")
+            .arg("--matrix")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let matrix = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = matrix.lines().collect();
+        assert_eq!(lines.len(), 22);
+        assert_eq!(lines[0], "21x21");
+        assert!(lines[1].starts_with("11111110") && lines[1].ends_with("01111111"));
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo matrix")
+            .arg("--png")
+            .arg("test_matrix.png")
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("test_matrix.png")
+            .arg("--matrix")
+            .arg("--no-content")
+            .assert()
+            .success()
+            .stdout(matrix.clone());
+
+        let output = qrscan()
+            .arg("test_matrix.png")
+            .arg("--matrix")
+            .arg("--json")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["matrix"]["width"], 21);
+        assert_eq!(json["matrix"]["height"], 21);
+        assert_eq!(json["matrix"]["modules"][0][0], 1);
+        assert_eq!(json["matrix"]["modules"][0][7], 0);
+
+        std::fs::remove_file("test_matrix.png").unwrap();
+    }
+
+    #[test]
+    fn test_strict() {
+        let opts = qrscan::RenderOptions {
+            ec_level: qrscan::EcLevel::L,
+            ..Default::default()
+        };
+        let mut image = qrscan::build_binary_image(b"foo strict", &opts).unwrap();
+        image.save("test_strict.png").unwrap();
+
+        qrscan()
+            .arg("test_strict.png")
+            .arg("--strict")
+            .assert()
+            .success()
+            .stdout("foo strict\n");
+
+        // Flip the modules of the first codewords, at the bottom right corner
+        for (x, y) in (0..16).flat_map(|x| (0..40).map(move |y| (x, y))) {
+            let pixel = image.get_pixel_mut(199 - x, 199 - y);
+            pixel.0[..3].iter_mut().for_each(|c| *c = 255 - *c);
+        }
+        image.save("test_strict.png").unwrap();
+
+        let output = qrscan()
+            .arg("test_strict.png")
+            .arg("--metadata")
+            .output()
+            .unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        assert!(output.contains("\nErrors Corrected: 2 of 3\n"));
+        assert!(output.ends_with("\nfoo strict\n"));
+
+        qrscan()
+            .arg("test_strict.png")
+            .arg("--strict")
+            .assert()
+            .failure()
+            .code(6)
+            .stderr(
+                "error: qrscan: test_strict.png: found a QR code but failed to decode it: 2 of the 3 correctable errors of a block were corrected, the QR code may be damaged\n",
+            );
+
+        // Beside an undamaged code, only printed with --all
+        let clean = qrscan::build_binary_image(b"foo", &opts).unwrap();
+        let mut side_by_side = image::RgbaImage::new(clean.width() * 2, clean.height());
+        image::imageops::replace(&mut side_by_side, &clean, 0, 0);
+        image::imageops::replace(&mut side_by_side, &image, clean.width() as i64, 0);
+        side_by_side.save("test_strict.png").unwrap();
+
+        qrscan()
+            .arg("test_strict.png")
+            .arg("--strict")
+            .assert()
+            .success()
+            .stdout("foo\n");
+
+        qrscan()
+            .arg("test_strict.png")
+            .arg("--strict")
+            .arg("--all")
+            .assert()
+            .code(6);
+
+        std::fs::remove_file("test_strict.png").unwrap();
+    }
+
+    #[test]
+    fn test_metadata_only() {
+        let opts = qrscan::RenderOptions {
+            module_size: 8,
+            ..Default::default()
+        };
+        let mut image = qrscan::build_binary_image(b"foo metadata", &opts).unwrap();
+        image.save("test_metadata_only.png").unwrap();
+
+        qrscan()
+            .arg("test_metadata_only.png")
+            .arg("--metadata-only")
+            .assert()
+            .success()
+            .stdout(
+                "Version: 1\nGrid Size: 21\nEC Level: 0\nMask: 2\nCapacity Bytes: 14\nErrors Corrected: 0 of 5\nBounds: 32,32 208,32 208,208 32,208\n",
+            );
+
+        // Wipe the data modules, leaving the patterns and the format information
+        for y in 32 + 9 * 8..32 + 21 * 8 {
+            for x in 32 + 9 * 8..32 + 21 * 8 {
+                image.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        image.save("test_metadata_only.png").unwrap();
+
+        qrscan().arg("test_metadata_only.png").assert().code(6);
+
+        let output = qrscan()
+            .arg("test_metadata_only.png")
+            .arg("--metadata-only")
+            .assert()
+            .success()
+            .stdout(
+                "Version: 1\nGrid Size: 21\nEC Level: 0\nMask: 2\nCapacity Bytes: 14\nBounds: 32,32 208,32 208,208 32,208\n",
+            )
+            .get_output()
+            .stderr
+            .clone();
+        let stderr = String::from_utf8(output).unwrap();
+        assert!(stderr.contains("warning: failed to decode the content: "));
+
+        std::fs::remove_file("test_metadata_only.png").unwrap();
+    }
+
+    #[test]
+    fn test_preview_size() {
+        use super::PreviewSize;
+
+        assert_eq!("40".parse(), Ok(PreviewSize::Cells(40)));
+        assert_eq!("80%".parse(), Ok(PreviewSize::Percent(80)));
+        assert!("0%".parse::<PreviewSize>().is_err());
+        assert!("120%".parse::<PreviewSize>().is_err());
+        assert!("wide".parse::<PreviewSize>().is_err());
+
+        assert_eq!(PreviewSize::Cells(40).resolve(120), 40);
+        assert_eq!(PreviewSize::Percent(80).resolve(120), 96);
+        assert_eq!(PreviewSize::Percent(1).resolve(50), 1);
+    }
+
+    #[test]
+    fn test_stdin_timeout() {
+        // Keep stdin open without writing to it
+        let mut child =
+            std::process::Command::new(assert_cmd::cargo::cargo_bin("qrscan"))
+                .env("XDG_CONFIG_HOME", "test_no_config_home")
+                .arg("-")
+                .arg("--stdin-timeout")
+                .arg("1")
+                .stdin(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+        let stdin = child.stdin.take();
+
+        let output = child.wait_with_output().unwrap();
+        drop(stdin);
+        assert_eq!(output.status.code(), Some(4));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .ends_with("error: qrscan: stdin was not closed within 1 seconds\n"));
+
+        qrscan()
+            .arg("--encode")
+            .arg("-")
+            .arg("--stdin-timeout")
+            .arg("1")
+            .write_stdin("foo")
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn test_stream() {
+        let png = |content: &str| {
+            qrscan::encode_to_png(content.as_bytes(), &Default::default()).unwrap()
+        };
+        let blank = {
+            let mut png = std::io::Cursor::new(vec![]);
+            image::RgbImage::from_pixel(100, 100, image::Rgb([255, 255, 255]))
+                .write_to(&mut png, image::ImageFormat::Png)
+                .unwrap();
+            png.into_inner()
+        };
+
+        // PNG images one after another, and a length prefixed one
+        let bar = png("bar");
+        let mut stream = png("foo");
+        stream.extend(&blank);
+        stream.extend((bar.len() as u32).to_be_bytes());
+        stream.extend(&bar);
+
+        qrscan()
+            .arg("--stream")
+            .write_stdin(stream.clone())
+            .assert()
+            .success()
+            .stdout("foo\nbar\n");
+
+        // The images that fail do not stop the stream, but set the exit code
+        let mut failing = png("foo");
+        failing.extend(4u32.to_be_bytes());
+        failing.extend(b"junk");
+        failing.extend(png("bar"));
+        let output = qrscan()
+            .arg("--stream")
+            .write_stdin(failing)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(output.stdout, b"foo\nbar\n");
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("error: qrscan: image 2: "));
+
+        stream.truncate(stream.len() - 10);
+        qrscan()
+            .arg("--stream")
+            .write_stdin(stream)
+            .assert()
+            .failure()
+            .code(1)
+            .stdout("foo\n");
+
+        // A remainder too short for a length prefix
+        let mut stream = png("foo");
+        stream.extend([0, 0]);
+        let output = qrscan()
+            .arg("--stream")
+            .write_stdin(stream)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(output.stdout, b"foo\n");
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .ends_with("error: qrscan: stdin ended in the middle of an image\n"));
+
+        qrscan()
+            .arg("--stream")
+            .write_stdin("hello world")
+            .assert()
+            .failure()
+            .code(8)
+            .stderr(
+                "error: qrscan: stdin did not contain PNG images or length-prefixed images\n",
+            );
+    }
+
+    #[test]
+    fn test_scan_not_an_image_from_stdin() {
+        qrscan()
+            .arg("-")
+            .write_stdin("")
+            .assert()
+            .code(8)
+            .stderr("error: qrscan: stdin is empty, expected an image\n");
+
+        qrscan()
+            .arg("-")
+            .write_stdin("hello world")
+            .assert()
+            .code(8)
+            .stderr("error: qrscan: stdin contained text, not an image (use --encode - to generate a QR code from it)\n");
+
+        qrscan()
+            .arg("-")
+            .write_stdin(b"\x00\xff\xfe".as_slice())
+            .assert()
+            .code(8)
+            .stderr("error: qrscan: stdin did not contain a decodable image\n");
+    }
+
+    #[test]
+    fn test_input_format() {
+        // TGA images have no signature to guess the format from
+        let png = qrscan::encode_to_png(b"foo tga", &Default::default()).unwrap();
+        image::load_from_memory(&png)
+            .unwrap()
+            .save_with_format("test_input_format.img", image::ImageFormat::Tga)
+            .unwrap();
+
+        qrscan().arg("test_input_format.img").assert().code(1);
+
+        qrscan()
+            .arg("test_input_format.img")
+            .arg("--input-format")
+            .arg("tga")
+            .assert()
+            .success()
+            .stdout("foo tga\n");
+
+        qrscan()
+            .arg("-")
+            .arg("--input-format")
+            .arg("TGA")
+            .pipe_stdin("test_input_format.img")
+            .unwrap()
+            .assert()
+            .success()
+            .stdout("foo tga\n");
+
+        qrscan()
+            .arg("-")
+            .arg("--input-format")
+            .arg("png")
+            .pipe_stdin("test_input_format.img")
+            .unwrap()
+            .assert()
+            .code(8);
+
+        std::fs::remove_file("test_input_format.img").unwrap();
+    }
+
+    #[test]
+    fn test_heif() {
+        use std::path::Path;
+
+        std::fs::write("test_heif.heic", b"\0\0\0\x18ftypheic\0\0\0\0mif1heic").unwrap();
+        std::fs::write("test_heif.mp4", b"\0\0\0\x18ftypisom\0\0\0\0isomiso2").unwrap();
+        assert!(super::is_heif(Path::new("test_heif.heic")).unwrap());
+        assert!(!super::is_heif(Path::new("test_heif.mp4")).unwrap());
+
+        // Not a real image, whether or not heif-convert is installed
+        let output = qrscan().arg("test_heif.heic").output().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("error: qrscan: test_heif.heic: "));
+        assert!(stderr.contains("heif-convert"));
+
+        // Found in directories too, whatever their extension
+        std::fs::create_dir_all("test_heif").unwrap();
+        std::fs::copy("test_heif.heic", "test_heif/photo.HEIC").unwrap();
+        std::fs::copy("test_heif.mp4", "test_heif/clip.bin").unwrap();
+        let mut images = vec![];
+        super::find_images(Path::new("test_heif"), &mut images).unwrap();
+        assert_eq!(images, [PathBuf::from("test_heif/photo.HEIC")]);
+        std::fs::remove_dir_all("test_heif").unwrap();
+
+        std::fs::remove_file("test_heif.heic").unwrap();
+        std::fs::remove_file("test_heif.mp4").unwrap();
+    }
+
+    #[test]
+    fn test_decode_method() {
+        let png = qrscan::encode_to_png(b"foo method", &Default::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        image
+            .save_with_format("test_decode_method.jpg", image::ImageFormat::Png)
+            .unwrap();
+        image.save("test_decode_method.tga").unwrap();
+
+        for (path, format, method) in [
+            ("test_decode_method.jpg", None, "png by its content"),
+            ("test_decode_method.tga", None, "tga by its extension"),
+            (
+                "test_decode_method.tga",
+                Some("tga"),
+                "tga as given with --input-format",
+            ),
+        ] {
+            let mut cmd = qrscan();
+            cmd.arg(path).arg("-v");
+            if let Some(format) = format {
+                cmd.arg("--input-format").arg(format);
+            }
+            let output = cmd.unwrap();
+            assert_eq!(output.stdout, b"foo method\n");
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains(&format!("debug: decoded the image as {method}\n")));
+        }
+
+        std::fs::remove_file("test_decode_method.jpg").unwrap();
+        std::fs::remove_file("test_decode_method.tga").unwrap();
+    }
+
+    #[test]
+    fn test_jobs() {
+        let paths: Vec<_> = (1..=6).map(|i| format!("test_jobs_{i}.png")).collect();
+        for (i, path) in paths.iter().enumerate() {
+            let content = format!("foo {}", i + 1);
+            let png = qrscan::encode_to_png(content.as_bytes(), &Default::default());
+            std::fs::write(path, png.unwrap()).unwrap();
+        }
+
+        let expected: String = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| format!("{path}:\nfoo {}\n", i + 1))
+            .collect();
+
+        for jobs in ["1", "4"] {
+            qrscan()
+                .args(&paths)
+                .arg("--jobs")
+                .arg(jobs)
+                .assert()
+                .success()
+                .stdout(expected.clone());
+        }
+
+        // The first error code, in the order of the paths
+        qrscan()
+            .arg(&paths[0])
+            .arg("test_jobs_missing.png")
+            .arg("/tmp")
+            .arg(&paths[1])
+            .arg("--jobs")
+            .arg("4")
+            .assert()
+            .code(3);
+
+        qrscan().arg("--jobs").arg("0").assert().code(2);
+
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scan_url() {
+        let output = qrscan()
+            .arg("http://localhost:1/code.png")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert_eq!(output.status.code(), Some(1));
+        assert!(stderr.starts_with("error: qrscan: http://localhost:1/code.png: "));
+
+        assert!(super::url_of(&PathBuf::from("https://example.com/code.png")).is_some());
+        assert!(super::url_of(&PathBuf::from("ftp://example.com/code.png")).is_none());
+        assert!(super::url_of(&PathBuf::from("http.png")).is_none());
+    }
+
+    #[test]
+    fn test_frame_path() {
+        use std::path::Path;
+
+        let path = super::frame_path(Path::new("frame.png"));
+        assert_eq!(path, Path::new("frame.png"));
+
+        let path = super::frame_path(Path::new("frame-{timestamp}.png"));
+        let name = path.to_str().unwrap();
+        assert!(name.starts_with("frame-2"));
+        assert!(name.ends_with(".png"));
+        assert!(!name.contains('{'));
+        assert!(!name.contains(':'));
+
+        qrscan().arg("--save-frame").assert().code(2);
+    }
+
+    #[test]
+    fn test_version_range() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--min-version")
+            .arg("3")
+            .arg("--png")
+            .arg("test_version_range.png")
+            .assert()
+            .success();
+
+        let output = qrscan()
+            .arg("test_version_range.png")
+            .arg("--metadata")
+            .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Version: 3\n"));
+
+        qrscan()
+            .arg("--encode")
+            .arg("a".repeat(100))
+            .arg("--max-version")
+            .arg("2")
+            .arg("--png")
+            .arg("test_version_range.png")
+            .assert()
+            .code(1)
+            .stderr("error: qrscan: the content needs version 6, above the maximum version 2\n");
+
+        qrscan()
+            .arg("--encode")
+            .arg("a".repeat(100))
+            .arg("--max-version")
+            .arg("1")
+            .assert()
+            .failure()
+            .code(1);
+
+        qrscan().arg("--max-version").arg("41").assert().code(2);
+
+        std::fs::remove_file("test_version_range.png").unwrap();
+    }
+
+    #[test]
+    fn test_split() {
+        let content = "0123456789".repeat(10);
+
+        qrscan()
+            .arg("--encode")
+            .arg(&content)
+            .arg("--split")
+            .arg("--max-version")
+            .arg("3")
+            .arg("--png")
+            .arg("test_split_{index}.png")
+            .assert()
+            .success();
+
+        let output = qrscan()
+            .arg("test_split_2.png")
+            .arg("--metadata")
+            .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\nPart: 2 of 3\n"));
+
+        // Side by side
+        let images: Vec<_> = (1..=3)
+            .map(|i| image::open(format!("test_split_{i}.png")).unwrap())
+            .collect();
+        let size = images[0].width();
+        let mut image = image::RgbaImage::new(size * 3, size);
+        for (i, part) in images.iter().enumerate() {
+            image::imageops::overlay(&mut image, part, (i as u32 * size) as i64, 0);
+        }
+        image.save("test_split_all.png").unwrap();
+
+        qrscan()
+            .arg("test_split_all.png")
+            .arg("--all")
+            .arg("--join")
+            .assert()
+            .success()
+            .stdout(format!("{content}\n"));
+
+        qrscan()
+            .arg("test_split_1.png")
+            .arg("--join")
+            .assert()
+            .code(1)
+            .stderr("error: qrscan: test_split_1.png: found only 1 of the 3 codes of the sequence\n");
+
+        qrscan()
+            .arg("--encode")
+            .arg(&content)
+            .arg("--split")
+            .arg("--png")
+            .arg("test_split.png")
+            .assert()
+            .code(1)
+            .stderr("error: qrscan: the path test_split.png must contain \"{index}\" to split the content\n");
+
+        for path in [
+            "test_split_1.png",
+            "test_split_2.png",
+            "test_split_3.png",
+            "test_split_all.png",
+        ] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_micro() {
+        qrscan()
+            .arg("--encode")
+            .arg("A-1234")
+            .arg("--micro")
+            .arg("--png")
+            .arg("test_micro.png")
+            .assert()
+            .success();
+
+        let output = qrscan()
+            .arg("test_micro.png")
+            .arg("--metadata")
+            .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Symbology: microqr\n"));
+        assert!(output.ends_with("\nA-1234\n"));
+
+        qrscan()
+            .arg("--encode")
+            .arg("A-1234")
+            .arg("--micro")
+            .arg("--split")
+            .assert()
+            .code(2);
+
+        std::fs::remove_file("test_micro.png").unwrap();
+    }
+
+    #[test]
+    fn test_mask() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--mask")
+            .arg("5")
+            .arg("--png")
+            .arg("test_mask.png")
+            .assert()
+            .success();
+
+        let output = qrscan()
+            .arg("test_mask.png")
+            .arg("--metadata")
+            .output()
+            .unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        assert!(output.contains("\nMask: 5\n"));
+        assert!(output.ends_with("\nfoo\n"));
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--mask")
+            .arg("8")
+            .assert()
+            .failure()
+            .code(2);
+
+        std::fs::remove_file("test_mask.png").unwrap();
+    }
+
+    #[test]
+    fn test_modules_range() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--png")
+            .arg("test_modules_range.png")
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("test_modules_range.png")
+            .arg("--max-modules")
+            .arg("25")
+            .assert()
+            .success()
+            .stdout("foo\n");
+
+        qrscan()
+            .arg("test_modules_range.png")
+            .arg("--min-modules")
+            .arg("25")
+            .assert()
+            .failure()
+            .code(5);
+
+        qrscan()
+            .arg("test_modules_range.png")
+            .arg("--min-modules")
+            .arg("29")
+            .arg("--max-modules")
+            .arg("25")
+            .assert()
+            .failure()
+            .code(2)
+            .stderr("error: qrscan: --min-modules 29 is above --max-modules 25\n");
+
+        std::fs::remove_file("test_modules_range.png").unwrap();
+    }
+
+    #[test]
+    fn test_max_dimension() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--module-size")
+            .arg("20")
+            .arg("--png")
+            .arg("test_max_dimension.png")
+            .assert()
+            .success();
+
+        let output = qrscan()
+            .arg("test_max_dimension.png")
+            .arg("--max-dimension")
+            .arg("200")
+            .arg("-vv")
+            .unwrap();
+        assert_eq!(output.stdout, b"foo\n");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("downscale to 200 pixels"));
+
+        qrscan()
+            .arg("test_max_dimension.png")
+            .arg("--max-dimension")
+            .arg("20")
+            .assert()
+            .failure()
+            .code(2);
+
+        qrscan()
+            .arg("test_max_dimension.png")
+            .arg("--min-size")
+            .arg("300")
+            .arg("--max-dimension")
+            .arg("200")
+            .assert()
+            .failure()
+            .code(2)
+            .stderr("error: qrscan: --min-size 300 is above --max-dimension 200\n");
+
+        qrscan()
+            .arg("test_max_dimension.png")
+            .arg("--min-size")
+            .arg("100000")
+            .assert()
+            .failure()
+            .code(2);
+
+        std::fs::remove_file("test_max_dimension.png").unwrap();
+    }
+
+    #[test]
+    fn test_deprecated_interval() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--inverval")
+            .arg("100")
+            .assert()
+            .success()
+            .stderr("warning: --inverval is deprecated, use --interval instead\n");
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--inverval")
+            .arg("100")
+            .arg("--interval")
+            .arg("100")
+            .assert()
+            .success()
+            .stderr("");
+
+        let help = qrscan().arg("--help").output().unwrap().stdout;
+        let help = String::from_utf8(help).unwrap();
+        assert!(help.contains("--interval"));
+        assert!(!help.contains("--inverval"));
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo transparent")
+            .arg("--bg")
+            .arg("#00000000")
+            .arg("--png")
+            .arg("test_transparent_background.png")
+            .arg("--verify")
+            .assert()
+            .success()
+            .stderr("");
+
+        qrscan()
+            .arg("test_transparent_background.png")
+            .assert()
+            .success()
+            .stdout("foo transparent\n");
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo transparent")
+            .arg("--bg")
+            .arg("#00000000")
+            .arg("--jpeg")
+            .arg("test_transparent_background.jpeg")
+            .assert()
+            .success()
+            .stderr(
+                "warning: JPEG has no transparency, exporting on a white background\n",
+            );
+
+        std::fs::remove_file("test_transparent_background.png").unwrap();
+        std::fs::remove_file("test_transparent_background.jpeg").unwrap();
+    }
+
+    #[test]
+    fn test_jpeg_quality() {
+        for quality in ["95", "20"] {
+            qrscan()
+                .arg("--encode")
+                .arg("foo jpeg quality")
+                .arg("--jpeg")
+                .arg(format!("test_jpeg_quality_{quality}.jpeg"))
+                .arg("--jpeg-quality")
+                .arg(quality)
+                .assert()
+                .success();
+
+            qrscan()
+                .arg(format!("test_jpeg_quality_{quality}.jpeg"))
+                .assert()
+                .success()
+                .stdout("foo jpeg quality\n");
+        }
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo jpeg quality")
+            .arg("--jpeg")
+            .arg("test_jpeg_quality_20.jpeg")
+            .arg("--jpeg-quality")
+            .arg("20")
+            .assert()
+            .success()
+            .stderr("warning: JPEG quality 20 may blur the QR code until it is unscannable\n");
+
+        let high = std::fs::metadata("test_jpeg_quality_95.jpeg")
+            .unwrap()
+            .len();
+        let low = std::fs::metadata("test_jpeg_quality_20.jpeg")
+            .unwrap()
+            .len();
+        assert!(low < high);
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--jpeg-quality")
+            .arg("0")
+            .assert()
+            .failure()
+            .code(2);
+
+        std::fs::remove_file("test_jpeg_quality_95.jpeg").unwrap();
+        std::fs::remove_file("test_jpeg_quality_20.jpeg").unwrap();
+    }
+
+    #[test]
+    fn test_verify() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo verify")
+            .arg("--verify")
+            .arg("--png")
+            .arg("test_verify.png")
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo verify ".repeat(10))
+            .arg("--split")
+            .arg("--max-version")
+            .arg("2")
+            .arg("--verify")
+            .assert()
+            .success();
+
+        // Too light to tell apart from the background
+        qrscan()
+            .arg("--encode")
+            .arg("foo verify")
+            .arg("--verify")
+            .arg("--fg")
+            .arg("#f8f8f8")
+            .arg("--png")
+            .arg("test_verify.png")
+            .assert()
+            .code(1)
+            .stderr("error: qrscan: verification failed: the QR code cannot be scanned back\n");
+
+        std::fs::remove_file("test_verify.png").unwrap();
+    }
+
+    #[test]
+    fn test_empty_content() {
+        qrencode::QrCode::new(b"")
+            .unwrap()
+            .render::<image::Luma<u8>>()
+            .build()
+            .save("test_empty_content.png")
+            .unwrap();
+
+        let output = qrscan().arg("test_empty_content.png").unwrap();
+        assert_eq!(output.stdout, b"\n");
+        assert!(output.stderr.ends_with(b"(empty content)\n"));
+
+        let output = qrscan()
+            .arg("test_empty_content.png")
+            .arg("--json")
+            .unwrap()
+            .stdout;
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["content"], "");
+        assert_eq!(json["version"], 1);
+    }
+
+    #[test]
+    fn test_encoding() {
+        use qrencode::bits::Bits;
+
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("日本語");
+        let mut bits = Bits::new(qrencode::Version::Normal(2));
+        bits.push_eci_designator(20).unwrap();
+        bits.push_byte_data(&sjis).unwrap();
+        bits.push_terminator(qrencode::EcLevel::L).unwrap();
+        qrencode::QrCode::with_bits(bits, qrencode::EcLevel::L)
+            .unwrap()
+            .render::<image::Luma<u8>>()
+            .build()
+            .save("test_encoding_sjis.png")
+            .unwrap();
+
+        qrscan()
+            .arg("test_encoding_sjis.png")
+            .assert()
+            .success()
+            .stdout("日本語\n");
+
+        let output = qrscan()
+            .arg("test_encoding_sjis.png")
+            .arg("--metadata")
+            .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\nEncoding: Shift_JIS\n"));
+
+        qrscan()
+            .arg("--encode")
+            .arg("-")
+            .arg("--png")
+            .arg("test_encoding_latin1.png")
+            .write_stdin(b"caf\xe9".as_slice())
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("test_encoding_latin1.png")
+            .assert()
+            .success()
+            .stdout("caf\u{fffd}\n");
+
+        qrscan()
+            .arg("test_encoding_latin1.png")
+            .arg("--strict-encoding")
+            .assert()
+            .code(6)
+            .stderr("error: qrscan: test_encoding_latin1.png: found a QR code but failed to decode it: the content is not valid UTF-8\n");
+
+        // Beside a valid code, only printed with --all
+        let valid = qrscan::build_binary_image(b"foo", &Default::default()).unwrap();
+        let invalid = image::open("test_encoding_latin1.png").unwrap();
+        let mut image = image::RgbaImage::new(valid.width() * 2, valid.height());
+        image::imageops::replace(&mut image, &valid, 0, 0);
+        image::imageops::replace(&mut image, &invalid, valid.width() as i64, 0);
+        image.save("test_encoding_latin1.png").unwrap();
+
+        qrscan()
+            .arg("test_encoding_latin1.png")
+            .arg("--strict-encoding")
+            .assert()
+            .success()
+            .stdout("foo\n");
+
+        qrscan()
+            .arg("test_encoding_latin1.png")
+            .arg("--strict-encoding")
+            .arg("--all")
+            .assert()
+            .code(6);
+
+        std::fs::remove_file("test_encoding_sjis.png").unwrap();
+        std::fs::remove_file("test_encoding_latin1.png").unwrap();
+    }
+
+    #[test]
+    fn test_symbology() {
+        use rxing::Writer;
+
+        let matrix = rxing::MultiFormatWriter
+            .encode("foo aztec", &rxing::BarcodeFormat::AZTEC, 0, 0)
+            .unwrap();
+        let (width, height) = (matrix.getWidth(), matrix.getHeight());
+        image::GrayImage::from_fn((width + 8) * 4, (height + 8) * 4, |x, y| {
+            let (x, y) = ((x / 4).wrapping_sub(4), (y / 4).wrapping_sub(4));
+            let dark = x < width && y < height && matrix.get(x, y);
+            image::Luma([if dark { 0 } else { 255 }])
+        })
+        .save("test_symbology.png")
+        .unwrap();
+
+        qrscan()
+            .arg("test_symbology.png")
+            .assert()
+            .success()
+            .stdout("foo aztec\n");
+
+        let output = qrscan()
+            .arg("test_symbology.png")
+            .arg("--metadata")
+            .arg("--no-content")
+            .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Symbology: aztec\nBounds: "));
+        assert!(output.ends_with("Type: text\n"));
+
+        qrscan()
+            .arg("test_symbology.png")
+            .arg("--symbology")
+            .arg("qr")
+            .assert()
+            .code(5);
+
+        qrscan()
+            .arg("test_symbology.png")
+            .arg("--symbology")
+            .arg("aztec")
+            .assert()
+            .success()
+            .stdout("foo aztec\n");
+
+        std::fs::remove_file("test_symbology.png").unwrap();
+    }
+
+    #[test]
+    fn test_barcode() {
+        use rxing::Writer;
+
+        let matrix = rxing::MultiFormatWriter
+            .encode("foo 128", &rxing::BarcodeFormat::CODE_128, 0, 0)
+            .unwrap();
+        let width = matrix.getWidth();
+        image::GrayImage::from_fn((width + 20) * 2, 60, |x, y| {
+            let x = (x / 2).wrapping_sub(10);
+            let dark = (10..50).contains(&y) && x < width && matrix.get(x, 0);
+            image::Luma([if dark { 0 } else { 255 }])
+        })
+        .save("test_barcode.png")
+        .unwrap();
+
+        qrscan().arg("test_barcode.png").assert().code(5);
+
+        let output = qrscan()
+            .arg("test_barcode.png")
+            .arg("--symbology")
+            .arg("code128")
+            .arg("--metadata")
+            .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Symbology: code128\nBounds: "));
+        assert!(output.ends_with("Type: text\n\nfoo 128\n"));
+
+        std::fs::remove_file("test_barcode.png").unwrap();
+    }
+
+    #[test]
+    fn test_scan_video() {
+        assert!(super::is_video(&PathBuf::from("clip.MP4")));
+        assert!(super::is_video(&PathBuf::from("dir/clip.webm")));
+        assert!(!super::is_video(&PathBuf::from("clip.png")));
+        assert!(!super::is_video(&PathBuf::from("mp4")));
+
+        std::fs::write("test_scan_video.mp4", "not really").unwrap();
+
+        // Fails either to run ffprobe or to parse the video
+        qrscan()
+            .arg("test_scan_video.mp4")
+            .assert()
+            .failure()
+            .code(1)
+            .stdout("");
+
+        std::fs::remove_file("test_scan_video.mp4").unwrap();
+
+        // Blank frames, from a stand-in for ffmpeg
+        std::fs::write("test_scan_video.raw", vec![255; 2 * 100 * 100 * 3]).unwrap();
+        let scan = |page: &str, script: &str| {
+            use clap::Parser;
+
+            let args = super::Args::parse_from(["qrscan", "--page", page]);
+            let ffmpeg = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(script)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+            super::scan_raw_frames(&args, ffmpeg, 100, 100).unwrap_err()
+        };
+
+        // Stopping early keeps the error of the frame, and stops the endless frames
+        let err = scan("1", "while cat test_scan_video.raw; do :; done");
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(qrscan::ScanError::NoCodeFound)
+        ));
+
+        // Running out of frames reports why ffmpeg ended them
+        let err = scan("3", "cat test_scan_video.raw; echo broken >&2; exit 1");
+        assert_eq!(err.to_string(), "ffmpeg: broken");
+
+        std::fs::remove_file("test_scan_video.raw").unwrap();
+    }
+
+    #[test]
+    fn test_scan_pdf() {
+        std::fs::write("test_scan_pdf.pdf", "%PDF-1.4\nnot really").unwrap();
+        assert!(super::is_pdf(&PathBuf::from("test_scan_pdf.pdf")).unwrap());
+
+        // Fails either to load the pdfium library or to parse the document
+        qrscan()
+            .arg("test_scan_pdf.pdf")
+            .assert()
+            .failure()
+            .code(1)
+            .stdout("");
+
+        std::fs::write("test_scan_pdf.pdf", "%PD").unwrap();
+        assert!(!super::is_pdf(&PathBuf::from("test_scan_pdf.pdf")).unwrap());
+
+        std::fs::remove_file("test_scan_pdf.pdf").unwrap();
+    }
+
+    #[test]
+    fn test_scan_pages() {
+        use clap::Parser;
+
+        let opts = qrscan::RenderOptions {
+            module_size: 1,
+            ..Default::default()
+        };
+        let mut damaged = qrscan::build_binary_image(b"foo pages", &opts).unwrap();
+        for y in 4 + 9..4 + 21 {
+            for x in 4 + 9..4 + 21 {
+                let pixel = damaged.get_pixel_mut(x, y);
+                pixel.0[..3].iter_mut().for_each(|c| *c = u8::MAX - *c);
+            }
+        }
+        let damaged = image::DynamicImage::ImageRgba8(damaged);
+        let blank = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            100,
+            100,
+            image::Rgba([255, 255, 255, 255]),
+        ));
+        let good = qrscan::encode_to_png(b"foo pages", &Default::default()).unwrap();
+        let good = image::load_from_memory(&good).unwrap();
+        let scan = |cli: &[&str], pages: Vec<image::DynamicImage>| {
+            let args = super::Args::parse_from(cli);
+            let images = pages
+                .into_iter()
+                .enumerate()
+                .map(|(i, image)| Ok((i + 1, image)));
+            super::scan_pages(&args, images)
+        };
+        let exit_code = |result: anyhow::Result<Vec<super::Scanned>>| {
+            super::exit_code(&result.err().unwrap())
+        };
+
+        // The undecodable code is reported over the page without one
+        let cli = ["qrscan", "--min-size", "200"];
+        assert_eq!(
+            exit_code(scan(&cli, vec![blank.clone(), damaged.clone()])),
+            6
+        );
+        assert_eq!(exit_code(scan(&cli, vec![blank.clone()])), 5);
+        let scanned = scan(&cli, vec![damaged.clone(), good.clone()]).unwrap();
+        assert_eq!(scanned.len(), 1);
+        assert_eq!(scanned[0].page, Some(2));
+
+        // With --page, the error of the page is returned as is
+        let cli = ["qrscan", "--min-size", "200", "--page", "1"];
+        assert_eq!(exit_code(scan(&cli, vec![damaged])), 6);
+    }
+
+    #[test]
+    fn test_scan_no_content() {
+        let file = TestFile::new("scan_no_content", "png");
+        qrscan()
+            .arg(&file.path)
+            .arg("-n")
+            .assert()
+            .success()
+            .stdout("");
+
+        qrscan()
+            .arg(&file.path)
+            .arg("--no-content")
+            .assert()
+            .success()
+            .stdout("");
+    }
+
+    #[test]
+    fn test_scan_all() {
+        let file = TestFile::new("scan_all", "png");
+        let code = image::open(&file.path).unwrap();
+        let mut both = image::RgbaImage::from_pixel(
+            code.width() * 2,
+            code.height(),
+            image::Rgba([255, 255, 255, 255]),
+        );
+        image::imageops::overlay(&mut both, &code.to_rgba8(), 0, 0);
+        image::imageops::overlay(&mut both, &code.to_rgba8(), code.width().into(), 0);
+        both.save("test_scan_all_both.png").unwrap();
+
+        qrscan()
+            .arg("test_scan_all_both.png")
+            .assert()
+            .success()
+            .stdout("foo png\n");
+
+        qrscan()
+            .arg("test_scan_all_both.png")
+            .arg("--all")
+            .assert()
+            .success()
+            .stdout("foo png\n\nfoo png\n");
+
+        std::fs::remove_file("test_scan_all_both.png").unwrap();
+    }
+
+    #[test]
+    fn test_scan_animated_gif() {
+        let frames = ["foo 1", "foo 1", "foo 2"].map(|content| {
+            let image =
+                qrscan::build_binary_image(content.as_bytes(), &Default::default());
+            image::Frame::new(image.unwrap())
+        });
+
+        let file = std::fs::File::create("test_scan_animated_gif.gif").unwrap();
+        let mut encoder = image::codecs::gif::GifEncoder::new(file);
+        encoder.encode_frames(frames).unwrap();
+        drop(encoder);
+
+        qrscan()
+            .arg("test_scan_animated_gif.gif")
+            .assert()
+            .success()
+            .stdout("foo 1\n");
+
+        qrscan()
+            .arg("test_scan_animated_gif.gif")
+            .arg("--all")
+            .assert()
+            .success()
+            .stdout("foo 1\n\nfoo 2\n");
+
+        qrscan()
+            .arg("test_scan_animated_gif.gif")
+            .arg("--frame")
+            .arg("3")
+            .assert()
+            .success()
+            .stdout("foo 2\n");
+
+        qrscan()
+            .arg("test_scan_animated_gif.gif")
+            .arg("--page")
+            .arg("4")
+            .assert()
+            .failure()
+            .code(1)
+            .stderr(
+                "error: qrscan: test_scan_animated_gif.gif: frame 4 is out of range, there are 3 frames\n",
+            );
+
+        qrscan()
+            .arg("test_scan_animated_gif.gif")
+            .arg("--page")
+            .arg("0")
+            .assert()
+            .failure()
+            .code(2);
+
+        std::fs::remove_file("test_scan_animated_gif.gif").unwrap();
+    }
+
+    #[test]
+    fn test_count() {
+        let code = qrscan::build_binary_image(b"foo", &Default::default()).unwrap();
+        let mut both = image::RgbaImage::from_pixel(
+            code.width() * 2,
+            code.height(),
+            image::Rgba([255, 255, 255, 255]),
+        );
+        image::imageops::overlay(&mut both, &code, 0, 0);
+        image::imageops::overlay(&mut both, &code, code.width().into(), 0);
+        both.save("test_count.png").unwrap();
+
+        qrscan()
+            .arg("test_count.png")
+            .arg("--count")
+            .assert()
+            .success()
+            .stdout("2\n");
+
+        qrscan()
+            .arg("test_count.png")
+            .arg("--count")
+            .arg("--expect")
+            .arg("3")
+            .assert()
+            .failure()
+            .code(7)
+            .stdout("2\n");
+
+        image::RgbImage::from_pixel(100, 100, image::Rgb([255, 255, 255]))
+            .save("test_count.png")
+            .unwrap();
+
+        qrscan()
+            .arg("test_count.png")
+            .arg("--count")
+            .assert()
+            .failure()
+            .code(5)
+            .stdout("0\n");
+
+        qrscan()
+            .arg("test_count.png")
+            .arg("--count")
+            .arg("--expect")
+            .arg("0")
+            .assert()
+            .success()
+            .stdout("0\n");
+
+        qrscan().arg("--expect").arg("1").assert().failure().code(2);
+
+        std::fs::remove_file("test_count.png").unwrap();
+    }
+
+    #[test]
+    fn test_scan_json() {
+        let file = TestFile::new("scan_json", "png");
+        let output = qrscan().arg(&file.path).arg("--json").output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(output.status.success());
+        assert!(stdout.starts_with(r#"{"content":"foo png","encoding":"utf-8","#));
+        assert!(stdout.ends_with("}\n"));
+
+        let output = qrscan()
+            .arg(&file.path)
+            .arg("--json")
+            .arg("--all")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(output.status.success());
+        assert!(stdout.starts_with(r#"[{"content":"foo png","#));
+        assert!(stdout.ends_with("}]\n"));
+    }
+
+    #[test]
+    fn test_scan_multiple_files() {
+        let png = TestFile::new("scan_multiple_files", "png");
+        let jpeg = TestFile::new("scan_multiple_files", "jpeg");
+
+        qrscan()
+            .arg(&png.path)
+            .arg("/foo/bar/doesntexists")
+            .arg(&jpeg.path)
+            .arg("--png")
+            .arg("test_{name}.out.png")
+            .assert()
+            .failure()
+            .code(3)
+            .stdout(
+                "test_scan_multiple_files.png:\nfoo png\ntest_scan_multiple_files.jpeg:\nfoo jpeg\n",
+            );
+
+        assert!(PathBuf::from("test_test_scan_multiple_files.out.png").exists());
+        std::fs::remove_file("test_test_scan_multiple_files.out.png").unwrap();
+    }
+
+    #[test]
+    fn test_scan_dir() {
+        std::fs::create_dir_all("test_scan_dir/nested").unwrap();
+        std::fs::write("test_scan_dir/notes.txt", "not an image").unwrap();
+
+        let file = TestFile::new("scan_dir", "png");
+        std::fs::copy(&file.path, "test_scan_dir/nested/code.png").unwrap();
+
+        qrscan()
+            .arg("test_scan_dir")
+            .arg("--recursive")
+            .assert()
+            .success()
+            .stdout("test_scan_dir/nested/code.png:\nfoo png\n");
+
+        qrscan().arg("test_scan_dir").assert().failure().code(2);
+
+        std::fs::remove_dir_all("test_scan_dir").unwrap();
+    }
+
+    #[test]
+    fn test_scan_dir_not_found() {
+        std::fs::create_dir_all("test_scan_dir_not_found").unwrap();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--png")
+            .arg("test_scan_dir_not_found/a.png")
+            .assert()
+            .success();
+
+        image::RgbImage::from_pixel(100, 100, image::Rgb([255, 255, 255]))
+            .save("test_scan_dir_not_found/b.png")
+            .unwrap();
+
+        qrscan()
+            .arg("test_scan_dir_not_found")
+            .arg("--recursive")
+            .arg("--quiet")
+            .assert()
+            .code(5)
+            .stdout("test_scan_dir_not_found/a.png:\nfoo\n");
+
+        std::fs::remove_dir_all("test_scan_dir_not_found").unwrap();
+    }
+
+    #[test]
+    fn test_scan_dir_progress() {
+        std::fs::create_dir_all("test_scan_dir_progress").unwrap();
+
+        for (i, content) in ["foo", "bar"].iter().enumerate() {
+            qrscan()
+                .arg("--encode")
+                .arg(content)
+                .arg("--png")
+                .arg(format!("test_scan_dir_progress/{i}.png"))
+                .assert()
+                .success();
+        }
+
+        // No progress line when stderr is not a terminal
+        let output = qrscan()
+            .arg("test_scan_dir_progress")
+            .arg("--recursive")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        assert!(!stderr.contains("files:"));
+        assert!(stderr.ends_with(
+            "Scanned 2 files in test_scan_dir_progress: 2 succeeded, 0 failed\n"
+        ));
+
+        std::fs::remove_dir_all("test_scan_dir_progress").unwrap();
+    }
+
+    #[test]
+    fn test_export_files() {
+        let file = TestFile::new("export_files", "png");
+        qrscan()
+            .arg(&file.path)
+            .arg("--ascii")
+            .arg("test.ascii")
+            .arg("--svg")
+            .arg("test.svg")
+            .arg("--jpeg")
+            .arg("test.jpeg")
+            .arg("--png")
+            .arg("test.png")
+            .arg("--webp")
+            .arg("test.webp")
+            .arg("--gif")
+            .arg("test.gif")
+            .arg("--bmp")
+            .arg("test.bmp")
+            .arg("--tiff")
+            .arg("test.tiff")
+            .arg("--pdf")
+            .arg("test.pdf")
+            .arg("--eps")
+            .arg("test.eps")
+            .assert()
+            .success()
+            .stdout("foo png\n");
+
+        assert!(PathBuf::from("test.ascii").exists());
+        assert!(PathBuf::from("test.svg").exists());
+        assert!(PathBuf::from("test.jpeg").exists());
+        assert!(PathBuf::from("test.png").exists());
+        assert!(PathBuf::from("test.webp").exists());
+        assert!(PathBuf::from("test.gif").exists());
+        assert!(PathBuf::from("test.bmp").exists());
+        assert!(PathBuf::from("test.tiff").exists());
+        assert!(PathBuf::from("test.pdf").exists());
+        assert!(PathBuf::from("test.eps").exists());
+
+        std::fs::remove_file("test.ascii").unwrap();
+        std::fs::remove_file("test.svg").unwrap();
+        std::fs::remove_file("test.jpeg").unwrap();
+        std::fs::remove_file("test.png").unwrap();
+        std::fs::remove_file("test.webp").unwrap();
+        std::fs::remove_file("test.gif").unwrap();
+        std::fs::remove_file("test.bmp").unwrap();
+        std::fs::remove_file("test.tiff").unwrap();
+        std::fs::remove_file("test.pdf").unwrap();
+        std::fs::remove_file("test.eps").unwrap();
+    }
+
+    #[test]
+    fn test_no_clobber() {
+        std::fs::write("test_no_clobber.svg", "keep").unwrap();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo clobber")
+            .arg("--svg")
+            .arg("test_no_clobber.svg")
+            .arg("--no-clobber")
+            .assert()
+            .code(1)
+            .stderr("error: qrscan: test_no_clobber.svg: already exists, not overwriting it with --no-clobber\n");
+        assert_eq!(
+            std::fs::read_to_string("test_no_clobber.svg").unwrap(),
+            "keep"
+        );
+
+        // Stdout is never clobbered
+        qrscan()
+            .arg("--encode")
+            .arg("foo clobber")
+            .arg("--svg")
+            .arg("-")
+            .arg("--no-clobber")
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo clobber")
+            .arg("--svg")
+            .arg("test_no_clobber.svg")
+            .assert()
+            .success();
+        assert_ne!(
+            std::fs::read_to_string("test_no_clobber.svg").unwrap(),
+            "keep"
+        );
+
+        std::fs::remove_file("test_no_clobber.svg").unwrap();
+    }
+
+    #[test]
+    fn test_bundle() {
+        let output = qrscan()
+            .arg("--encode")
+            .arg("foo bundle")
+            .arg("--png")
+            .arg("test_bundle/{name}.png")
+            .arg("--svg")
+            .arg("test_bundle.svg")
+            .arg("--bundle")
+            .arg("-")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.starts_with(b"PK\x03\x04"));
+
+        // The files are only in the archive
+        let names = String::from_utf8_lossy(&output.stdout);
+        assert!(names.contains("test_bundle/qrcode.png"));
+        assert!(names.contains("test_bundle.svg"));
+        assert!(!PathBuf::from("test_bundle").exists());
+        assert!(!PathBuf::from("test_bundle.svg").exists());
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo bundle")
+            .arg("--png")
+            .arg("-")
+            .arg("--bundle")
+            .arg("test_bundle.zip")
+            .assert()
+            .code(1)
+            .stderr("error: qrscan: -: not a file name to write into the bundle\n");
+        assert!(!PathBuf::from("test_bundle.zip").exists());
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo bundle")
+            .arg("--bundle")
+            .arg("test_bundle.zip")
+            .assert()
+            .code(2)
+            .stderr("error: qrscan: --bundle needs a format to export, like --png or --svg\n");
+    }
+
+    #[test]
+    fn test_eps() {
+        let output = qrscan()
+            .arg("--encode")
+            .arg("foo eps")
+            .arg("--fg")
+            .arg("#00f")
+            .arg("--no-quiet-zone")
+            .arg("--eps")
+            .arg("-")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let eps = String::from_utf8(output).unwrap();
+        assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 168 168\n"));
+        assert!(eps.contains("0.000 0.000 1.000 setrgbcolor\n"));
+        assert!(eps.contains("\n0 160 56 8 rectfill\n"));
+        assert!(eps.ends_with("%%EOF\n"));
+    }
+
+    #[test]
+    fn test_err_no_such_camera() {
+        qrscan()
+            .arg("--device")
+            .arg("doesntexist")
+            .assert()
+            .failure()
+            .code(1);
+    }
+
+    #[test]
+    fn test_list_cameras_json() {
+        let camera = super::JsonCamera {
+            index: "0".into(),
+            name: "Webcam".into(),
+            formats: vec![super::JsonCameraFormat {
+                format: "MJPEG".into(),
+                width: 1920,
+                height: 1080,
+                frame_rates: vec![30, 15],
+            }],
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&camera).unwrap(),
+            r#"{"index":"0","name":"Webcam","formats":[{"format":"MJPEG","width":1920,"height":1080,"frame_rates":[30,15]}]}"#
+        );
+
+        // Whether or not there are cameras to list
+        let output = qrscan()
+            .arg("--list-cameras")
+            .arg("--json")
+            .output()
+            .unwrap();
+        if output.status.success() {
+            let json: serde_json::Value =
+                serde_json::from_slice(&output.stdout).unwrap();
+            assert!(json.is_array());
+        } else {
+            assert_eq!(output.status.code(), Some(1));
+        }
+    }
+
+    #[test]
+    fn test_device_auto() {
+        let gray = image::RgbImage::from_pixel(64, 48, image::Rgb([40, 40, 40]));
+        assert!(super::is_blank(&gray.into()));
+        let png = qrscan::encode_to_png(b"foo auto", &Default::default()).unwrap();
+        assert!(!super::is_blank(&image::load_from_memory(&png).unwrap()));
+
+        // Without a camera giving frames, camera 0 is tried anyway
+        let output = qrscan()
+            .arg("--device")
+            .arg("auto")
+            .arg("--open-retries")
+            .arg("0")
+            .arg("--timeout")
+            .arg("1")
+            .arg("-v")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("debug: falling back to camera 0")
+                || stderr.contains("debug: using camera")
+        );
+    }
+
+    #[test]
+    fn test_wait_for_device() {
+        let output = qrscan()
+            .arg("--device")
+            .arg("/dev/video99")
+            .arg("--open-retries")
+            .arg("0")
+            .arg("--wait-for-device")
+            .arg("2")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(4));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("Waiting for the camera to be plugged in\n"));
+        assert!(stderr
+            .ends_with("error: qrscan: no camera was plugged in within 2 seconds\n"));
+    }
+
+    #[test]
+    fn test_open_retries() {
+        let output = qrscan()
+            .arg("--device")
+            .arg("/dev/video99")
+            .arg("--open-retries")
+            .arg("2")
+            .arg("-v")
+            .assert()
+            .failure()
+            .code(1)
+            .get_output()
+            .stderr
+            .clone();
+        let stderr = String::from_utf8(output).unwrap();
+        assert!(stderr.contains("retrying in 250ms (1/2)"));
+        assert!(stderr.contains("retrying in 500ms (2/2)"));
+        assert!(!stderr.contains("(3/2)"));
+
+        let output = qrscan()
+            .arg("--device")
+            .arg("/dev/video99")
+            .arg("--open-retries")
+            .arg("0")
+            .arg("-v")
+            .assert()
+            .failure()
+            .get_output()
+            .stderr
+            .clone();
+        assert!(!String::from_utf8(output).unwrap().contains("retrying"));
+    }
+
+    #[test]
+    fn test_warmup() {
+        qrscan().arg("--warmup").arg("-1").assert().code(2);
+        qrscan().arg("--warmup").arg("many").assert().code(2);
+    }
+
+    #[test]
+    fn test_drop_frames() {
+        qrscan().arg("--drop-frames").arg("-1").assert().code(2);
+        qrscan().arg("--drop-frames").arg("many").assert().code(2);
+    }
+
+    #[test]
+    fn test_camera_width_requires_height() {
+        qrscan()
+            .arg("--camera-width")
+            .arg("1920")
+            .assert()
+            .failure();
+        qrscan()
+            .arg("--camera-height")
+            .arg("1080")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_invalid_frame_format() {
+        qrscan().arg("--frame-format").arg("gif").assert().failure();
+    }
+
+    #[test]
+    fn test_no_color() {
+        // Not a terminal
+        let output = qrscan().arg("--encode").arg("foo").unwrap().stdout;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains('#'));
+        assert!(!output.contains('█'));
+
+        let output = qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--no-color")
+            .env("NO_COLOR", "1")
+            .unwrap()
+            .stdout;
+        assert!(String::from_utf8(output).unwrap().is_ascii());
+    }
+
+    #[test]
+    fn test_qr_style() {
+        let print = |style: &str| {
+            let output = qrscan()
+                .arg("--encode")
+                .arg("foo")
+                .arg("--qr-style")
+                .arg(style)
+                .unwrap()
+                .stdout;
+            String::from_utf8(output).unwrap()
+        };
+
+        assert!(print("unicode").contains('█'));
+        assert!(print("ascii").contains('#'));
+        assert!(print("braille").contains('\u{28ff}'));
+        assert!(print("auto").is_ascii());
+    }
+
+    #[test]
+    fn test_output() {
+        // Two codes side by side
+        let opts = Default::default();
+        let foo = qrscan::build_binary_image(b"foo output", &opts).unwrap();
+        let bar = qrscan::build_binary_image(b"bar output", &opts).unwrap();
+        let mut image = image::RgbaImage::new(foo.width() * 2, foo.height());
+        image::imageops::replace(&mut image, &foo, 0, 0);
+        image::imageops::replace(&mut image, &bar, foo.width() as i64, 0);
+        image.save("test_output.png").unwrap();
+
+        qrscan()
+            .arg("test_output.png")
+            .arg("--all")
+            .arg("--output")
+            .arg("test_output.txt")
+            .assert()
+            .success();
+
+        let mut lines: Vec<_> = std::fs::read_to_string("test_output.txt")
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, ["bar output", "foo output"]);
+
+        qrscan()
+            .arg("test_output.png")
+            .arg("--all")
+            .arg("--output")
+            .arg("test_output_{index}.txt")
+            .assert()
+            .success();
+
+        let mut contents = [1, 2].map(|i| {
+            let path = format!("test_output_{i}.txt");
+            let content = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            content
+        });
+        contents.sort();
+        assert_eq!(contents, ["bar output", "foo output"]);
+
+        qrscan()
+            .arg("test_output.png")
+            .arg("--output")
+            .arg("-")
+            .assert()
+            .success()
+            .stdout("foo output\n");
+
+        std::fs::remove_file("test_output.png").unwrap();
+        std::fs::remove_file("test_output.txt").unwrap();
+    }
+
+    #[test]
+    fn test_include_failed() {
+        // A code and a damaged one side by side
+        let opts = Default::default();
+        let good = qrscan::build_binary_image(b"foo failed", &opts).unwrap();
+        let mut damaged = qrscan::build_binary_image(b"bar failed", &opts).unwrap();
+        let quiet_zone = 4 * 8;
+        for y in quiet_zone + 9 * 8..quiet_zone + 21 * 8 {
+            for x in quiet_zone + 9 * 8..quiet_zone + 21 * 8 {
+                damaged.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        let mut image = image::RgbaImage::new(good.width() * 2, good.height());
+        image::imageops::replace(&mut image, &good, 0, 0);
+        image::imageops::replace(&mut image, &damaged, good.width() as i64, 0);
+        image.save("test_include_failed.png").unwrap();
+        damaged.save("test_include_failed_damaged.png").unwrap();
+
+        let output = qrscan()
+            .arg("test_include_failed.png")
+            .arg("--json")
+            .arg("--all")
+            .arg("--include-failed")
+            .unwrap()
+            .stdout;
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[0]["decoded"], true);
+        assert_eq!(json[0]["content"], "foo failed");
+        assert_eq!(json[1]["decoded"], false);
+        assert!(json[1]["content"].is_null());
+        assert!(!json[1]["error"].as_str().unwrap().is_empty());
+        assert!(json[1]["bounds"][0][0].as_i64().unwrap() >= good.width().into());
+
+        // Still an error when none of the codes decoded
+        let output = qrscan()
+            .arg("test_include_failed_damaged.png")
+            .arg("--json")
+            .arg("--all")
+            .arg("--include-failed")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(6));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["decoded"], false);
+
+        // Without the flag, the failed codes are left out
+        let output = qrscan()
+            .arg("test_include_failed.png")
+            .arg("--json")
+            .arg("--all")
+            .unwrap()
+            .stdout;
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert!(json[0].get("decoded").is_none());
+
+        qrscan()
+            .arg("test_include_failed.png")
+            .arg("--json")
+            .arg("--include-failed")
+            .assert()
+            .failure()
+            .code(2);
+
+        // The rejections of the decoded codes are not taken for failed grids
+        let invalid = qrscan::build_binary_image(b"caf\xe9 failed", &opts).unwrap();
+        image::imageops::replace(&mut image, &invalid, 0, 0);
+        image.save("test_include_failed.png").unwrap();
+        let output = qrscan()
+            .arg("test_include_failed.png")
+            .arg("--json")
+            .arg("--all")
+            .arg("--include-failed")
+            .arg("--strict-encoding")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(6));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("the content is not valid UTF-8"));
+
+        std::fs::remove_file("test_include_failed.png").unwrap();
+        std::fs::remove_file("test_include_failed_damaged.png").unwrap();
+    }
+
+    #[test]
+    fn test_null() {
+        // Two codes side by side
+        let opts = Default::default();
+        let foo = qrscan::build_binary_image(b"foo null", &opts).unwrap();
+        let bar = qrscan::build_binary_image(b"bar null", &opts).unwrap();
+        let mut image = image::RgbaImage::new(foo.width() * 2, foo.height());
+        image::imageops::replace(&mut image, &foo, 0, 0);
+        image::imageops::replace(&mut image, &bar, foo.width() as i64, 0);
+        image.save("test_null.png").unwrap();
+
+        let output = qrscan()
+            .arg("test_null.png")
+            .arg("--all")
+            .arg("-0")
+            .unwrap();
+        let mut contents: Vec<_> = output.stdout.split_inclusive(|&b| b == 0).collect();
+        contents.sort();
+        assert_eq!(contents, [&b"bar null\0"[..], b"foo null\0"]);
+
+        qrscan()
+            .arg("test_null.png")
+            .arg("--no-newline")
+            .assert()
+            .success()
+            .stdout("foo null");
+
+        qrscan()
+            .arg("test_null.png")
+            .arg("--null")
+            .arg("--output")
+            .arg("-")
+            .assert()
+            .success()
+            .stdout("foo null\0");
+
+        qrscan()
+            .arg("test_null.png")
+            .arg("--null")
+            .arg("--no-newline")
+            .assert()
+            .failure()
+            .code(2);
+
+        std::fs::remove_file("test_null.png").unwrap();
+    }
+
+    #[test]
+    fn test_encode() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo encode")
+            .arg("--png")
+            .arg("test_encode.png")
+            .assert()
+            .success()
+            .stdout("");
+
+        qrscan()
+            .arg("test_encode.png")
+            .assert()
+            .success()
+            .stdout("foo encode\n");
+
+        let png = qrscan()
+            .arg("--encode")
+            .arg("-")
+            .arg("--png")
+            .arg("-")
+            .write_stdin("foo stdin")
+            .output()
+            .unwrap()
+            .stdout;
+
+        qrscan()
+            .arg("-")
+            .write_stdin(png)
+            .assert()
+            .success()
+            .stdout("foo stdin\n");
+
+        std::fs::remove_file("test_encode.png").unwrap();
+    }
+
+    #[test]
+    fn test_url_decode() {
+        let png = qrscan()
+            .arg("--encode")
+            .arg("https://example.com/foo%20bar")
+            .arg("--png")
+            .arg("-")
+            .output()
+            .unwrap()
+            .stdout;
+
+        qrscan()
+            .arg("-")
+            .arg("--url-decode")
+            .write_stdin(png.clone())
+            .assert()
+            .success()
+            .stdout("https://example.com/foo bar\n");
+
+        qrscan()
+            .arg("-")
+            .write_stdin(png)
+            .assert()
+            .success()
+            .stdout("https://example.com/foo%20bar\n");
+    }
+
+    #[test]
+    fn test_parse_wifi() {
+        let png = qrscan()
+            .arg("--encode")
+            .arg("WIFI:T:WPA;S:foo\\;wifi;P:bar;;")
+            .arg("--png")
+            .arg("-")
+            .output()
+            .unwrap()
+            .stdout;
+
+        qrscan()
+            .arg("-")
+            .arg("--parse")
+            .write_stdin(png.clone())
+            .assert()
+            .success()
+            .stdout(
+                "SSID: foo;wifi\nAuthentication: WPA\nPassword: bar\nHidden: false\n",
+            );
+
+        let output = qrscan()
+            .arg("-")
+            .arg("--json")
+            .write_stdin(png)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(output.status.success());
+        assert!(stdout.ends_with(
+            r#""type":"wifi","wifi":{"ssid":"foo;wifi","auth":"WPA","password":"bar","hidden":false}}
+"#
+        ));
+    }
+
+    #[test]
+    fn test_open() {
+        assert!(super::is_openable_url("https://example.com/?q=foo&bar=1"));
+        assert!(super::is_openable_url("HTTP://example.com"));
+        assert!(!super::is_openable_url("file:///etc/passwd"));
+        assert!(!super::is_openable_url("javascript:alert(1)"));
+        assert!(!super::is_openable_url(" https://example.com"));
+        assert!(!super::is_openable_url("https://example.com\n--foo"));
+        assert!(!super::is_openable_url("https://example.com foo"));
+
+        let file = TestFile::new("open", "png");
+        let output = qrscan().arg(&file.path).arg("--open").output().unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"foo png\n");
+        assert!(stderr
+            .ends_with("not opening the content as it is not an http or https URL\n"));
+    }
+
+    #[test]
+    fn test_notification_body() {
+        assert_eq!(super::notification_body(b"foo"), "foo");
+        assert_eq!(super::notification_body(b"foo\xff"), "foo\u{fffd}");
+
+        let long = "ü".repeat(super::NOTIFY_MAX_CHARS + 1);
+        let body = super::notification_body(long.as_bytes());
+        assert_eq!(body.chars().count(), super::NOTIFY_MAX_CHARS + 1);
+        assert!(body.ends_with("ü…"));
+    }
+
+    #[test]
+    fn test_crop() {
+        let file = TestFile::new("crop", "png");
+
+        let output = qrscan()
+            .arg(&file.path)
+            .arg("--crop")
+            .arg("0,0,100000,100000")
+            .arg("--json")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        assert!(stdout.starts_with(r#"{"content":"foo png","#));
+        assert!(stderr.starts_with("warning: crop region clamped to 0,0,"));
+
+        qrscan()
+            .arg(&file.path)
+            .arg("--crop")
+            .arg("0,0,10,10")
+            .assert()
+            .failure()
+            .code(5)
+            .stdout("");
+
+        qrscan()
+            .arg(&file.path)
+            .arg("--crop")
+            .arg("100000,0,10,10")
+            .assert()
+            .failure()
+            .code(1)
+            .stdout("");
+
+        qrscan()
+            .arg(&file.path)
+            .arg("--crop")
+            .arg("0,0,10")
+            .assert()
+            .failure()
+            .code(2)
+            .stdout("");
+    }
+
+    #[test]
+    fn test_raw() {
+        let file = TestFile::new("raw", "png");
+
+        qrscan()
+            .arg(&file.path)
+            .arg("--raw")
+            .arg("-")
+            .assert()
+            .success()
+            .stdout("foo png");
+
+        qrscan()
+            .arg(&file.path)
+            .arg("--raw")
+            .arg("test_raw.bin")
+            .assert()
+            .success()
+            .stdout("foo png\n");
+
+        assert_eq!(std::fs::read("test_raw.bin").unwrap(), b"foo png");
+        std::fs::remove_file("test_raw.bin").unwrap();
     }
 
-    std::process::exit(rc);
-}
+    #[test]
+    fn test_quiet() {
+        let file = TestFile::new("quiet", "png");
 
-#[cfg(test)]
-mod tests {
-    use std::path::PathBuf;
+        qrscan()
+            .arg(&file.path)
+            .arg("--quiet")
+            .assert()
+            .success()
+            .stdout("foo png\n")
+            .stderr("");
+    }
 
-    use assert_cmd::prelude::OutputOkExt;
+    #[test]
+    fn test_beep() {
+        let png = qrscan()
+            .arg("--encode")
+            .arg("foo beep")
+            .arg("--png")
+            .arg("-")
+            .output()
+            .unwrap()
+            .stdout;
 
-    fn qrscan() -> assert_cmd::Command {
-        assert_cmd::Command::cargo_bin("qrscan").unwrap()
+        let output = qrscan()
+            .arg("-")
+            .arg("--beep")
+            .write_stdin(png)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"foo beep\n");
+        assert!(output.stderr.ends_with(b"\x07"));
     }
 
-    pub struct TestFile {
-        pub path: PathBuf,
+    #[test]
+    fn test_verbose() {
+        let png = qrscan()
+            .arg("--encode")
+            .arg("foo verbose")
+            .arg("--png")
+            .arg("-")
+            .output()
+            .unwrap()
+            .stdout;
+
+        let output = qrscan()
+            .arg("-")
+            .arg("-vv")
+            .arg("--no-rotate")
+            .arg("--threshold")
+            .arg("128")
+            .write_stdin(png.clone())
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"foo verbose\n");
+        assert!(stderr.contains("debug: scanning 232x232 image\n"));
+        assert!(stderr.contains("debug: preprocessing: threshold at 128\n"));
+        assert!(stderr.contains("debug: detected 1 QR codes in "));
+        assert!(stderr.contains("debug: decoded 1 QR codes in "));
+
+        let output = qrscan()
+            .arg("-")
+            .arg("-v")
+            .write_stdin(png)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(stderr.contains("debug: scanning 232x232 image\n"));
+        assert!(!stderr.contains("debug: preprocessing: "));
     }
 
-    impl TestFile {
-        pub fn new(id: &str, ext: &str) -> Self {
-            let path = PathBuf::from(format!("test_{id}.{}", ext));
-            let header = format!("Accept: image/{}", ext);
-            let data = format!("foo {}", ext);
+    #[test]
+    fn test_module_size() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--module-size")
+            .arg("1")
+            .arg("--png")
+            .arg("test_module_size.png")
+            .assert()
+            .success();
 
-            std::process::Command::new("curl")
-                .arg("https://qrcode.show")
-                .arg("-k")
-                .arg("-H")
-                .arg(&header)
-                .arg("-d")
-                .arg(&data)
-                .arg("-o")
-                .arg(&path)
-                .unwrap();
+        let image = image::open("test_module_size.png").unwrap();
+        assert_eq!(image.width(), 29);
+        assert_eq!(image.height(), 29);
 
-            Self { path }
-        }
+        std::fs::remove_file("test_module_size.png").unwrap();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--module-size")
+            .arg("1000")
+            .assert()
+            .failure();
     }
 
-    impl Drop for TestFile {
-        fn drop(&mut self) {
-            std::fs::remove_file(&self.path).unwrap();
+    #[test]
+    fn test_quiet_zone_size() {
+        for (size, width) in [("0", 21), ("10", 41)] {
+            qrscan()
+                .arg("--encode")
+                .arg("foo")
+                .arg("--module-size")
+                .arg("1")
+                .arg("--quiet-zone-size")
+                .arg(size)
+                .arg("--png")
+                .arg("test_quiet_zone_size.png")
+                .assert()
+                .success();
+
+            let image = image::open("test_quiet_zone_size.png").unwrap();
+            assert_eq!(image.width(), width);
+            assert_eq!(image.height(), width);
         }
+
+        std::fs::remove_file("test_quiet_zone_size.png").unwrap();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--quiet-zone-size")
+            .arg("8")
+            .arg("--no-quiet-zone")
+            .assert()
+            .failure();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--quiet-zone-size")
+            .arg("4000000000")
+            .assert()
+            .failure()
+            .code(2);
     }
 
     #[test]
-    fn test_help() {
-        qrscan().arg("-h").assert().success();
-        qrscan().arg("--help").assert().success();
+    fn test_config() {
+        std::fs::create_dir_all("test_config/qrscan").unwrap();
+        std::fs::write(
+            "test_config/qrscan/config.toml",
+            "module_size = 1\nfg = \"#f00\"\nno_rotate = true\n",
+        )
+        .unwrap();
+
+        let width = |args: &[&str]| {
+            let png = qrscan()
+                .env("XDG_CONFIG_HOME", "test_config")
+                .arg("--encode")
+                .arg("foo")
+                .arg("--png")
+                .arg("-")
+                .args(args)
+                .output()
+                .unwrap()
+                .stdout;
+            image::load_from_memory(&png).unwrap().width()
+        };
+
+        assert_eq!(width(&[]), 29);
+        assert_eq!(width(&["--module-size", "2"]), 58);
+        assert_eq!(width(&["--no-config"]), 232);
+        assert_eq!(width(&["--size", "100"]), 100);
+
+        std::fs::rename("test_config/qrscan/config.toml", "test_config/qrscan.toml")
+            .unwrap();
+        assert_eq!(width(&[]), 232);
+        assert_eq!(width(&["--config", "test_config/qrscan.toml"]), 29);
+
+        std::fs::write("test_config/qrscan.toml", "foo = 1\n").unwrap();
+        qrscan()
+            .arg("--config=test_config/qrscan.toml")
+            .assert()
+            .failure()
+            .code(2)
+            .stderr("error: qrscan: test_config/qrscan.toml: unknown option: foo\n");
+
+        std::fs::write("test_config/qrscan.toml", "verbose = 2\n").unwrap();
+        let output = qrscan()
+            .arg("--config=test_config/qrscan.toml")
+            .arg("-")
+            .write_stdin(qrscan::encode_to_png(b"foo", &Default::default()).unwrap())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("debug: preprocessing: "));
+
+        std::fs::write("test_config/qrscan.toml", "verbose = -1\n").unwrap();
+        qrscan()
+            .arg("--config=test_config/qrscan.toml")
+            .assert()
+            .failure()
+            .code(2)
+            .stderr(
+                "error: qrscan: test_config/qrscan.toml: verbose: expected a count from 0 to 255\n",
+            );
+
+        std::fs::write("test_config/qrscan.toml", "quiet = true\n").unwrap();
+        qrscan()
+            .arg("--config=test_config/qrscan.toml")
+            .arg("--encode")
+            .arg("foo")
+            .arg("-v")
+            .assert()
+            .success();
+
+        std::fs::remove_dir_all("test_config").unwrap();
     }
 
     #[test]
-    fn test_scan_jpeg_file() {
-        let file = TestFile::new("scan_jpeg_file", "jpeg");
+    fn test_env() {
+        std::fs::create_dir_all("test_env/qrscan").unwrap();
+        std::fs::write("test_env/qrscan/config.toml", "module_size = 1\n").unwrap();
+
+        let width = |env: &[(&str, &str)], args: &[&str]| {
+            let png = qrscan()
+                .env("XDG_CONFIG_HOME", "test_env")
+                .envs(env.iter().copied())
+                .arg("--encode")
+                .arg("foo")
+                .arg("--png")
+                .arg("-")
+                .args(args)
+                .output()
+                .unwrap()
+                .stdout;
+            image::load_from_memory(&png).unwrap().width()
+        };
+
+        assert_eq!(width(&[], &[]), 29);
+        assert_eq!(width(&[("QRSCAN_MODULE_SIZE", "2")], &[]), 58);
+        assert_eq!(
+            width(&[("QRSCAN_MODULE_SIZE", "2")], &["--module-size", "3"]),
+            87
+        );
+        assert_eq!(width(&[("QRSCAN_NO_CONFIG", "1")], &[]), 232);
+        assert_eq!(width(&[("QRSCAN_NO_QUIET_ZONE", "true")], &[]), 21);
+        assert_eq!(width(&[("QRSCAN_NO_QUIET_ZONE", "0")], &[]), 29);
+        assert_eq!(
+            width(&[("QRSCAN_MODULE_SIZE", "2")], &["--size", "100"]),
+            100
+        );
+
         qrscan()
-            .arg(&file.path)
+            .env("XDG_CONFIG_HOME", "test_env")
+            .env("QRSCAN_QUIET", "1")
+            .arg("--encode")
+            .arg("foo")
+            .arg("-v")
             .assert()
-            .success()
-            .stdout("foo jpeg\n");
+            .success();
+
+        qrscan()
+            .env("QRSCAN_MODULE_SIZE", "foo")
+            .arg("--encode")
+            .arg("foo")
+            .assert()
+            .failure()
+            .code(2);
+
+        std::fs::remove_dir_all("test_env").unwrap();
     }
 
     #[test]
-    fn test_scan_png_file() {
-        let file = TestFile::new("scan_png_file", "png");
+    fn test_logo() {
+        image::RgbImage::from_pixel(10, 10, image::Rgb([255, 0, 0]))
+            .save("test_logo_in.png")
+            .unwrap();
+
         qrscan()
-            .arg(&file.path)
+            .arg("--encode")
+            .arg("foo logo")
+            .arg("--logo")
+            .arg("test_logo_in.png")
+            .arg("--png")
+            .arg("test_logo.png")
             .assert()
             .success()
-            .stdout("foo png\n");
+            .stderr("");
+
+        qrscan()
+            .arg("test_logo.png")
+            .assert()
+            .success()
+            .stdout("foo logo\n");
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo logo")
+            .arg("--logo")
+            .arg("test_logo_in.png")
+            .arg("--logo-size")
+            .arg("50")
+            .arg("--png")
+            .arg("test_logo.png")
+            .assert()
+            .success()
+            .stderr(
+                "warning: a logo wider than 30% of the QR code may make it unreadable\n",
+            );
+
+        let output = qrscan()
+            .arg("--encode")
+            .arg("foo logo")
+            .arg("--logo")
+            .arg("test_logo_missing.png")
+            .arg("--png")
+            .arg("test_logo.png")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("error: qrscan: test_logo_missing.png: "));
+
+        // Not loaded when nothing is exported
+        qrscan()
+            .arg("-")
+            .arg("--logo")
+            .arg("test_logo_missing.png")
+            .write_stdin(
+                qrscan::encode_to_png(b"foo logo", &Default::default()).unwrap(),
+            )
+            .assert()
+            .success()
+            .stdout("foo logo\n");
+
+        std::fs::remove_file("test_logo_in.png").unwrap();
+        std::fs::remove_file("test_logo.png").unwrap();
     }
 
     #[test]
-    fn test_scan_from_stdin() {
-        let file = TestFile::new("scan_from_stdin", "png");
+    fn test_timestamps() {
         qrscan()
-            .arg("-")
-            .pipe_stdin(&file.path)
+            .arg("--encode")
+            .arg("foo timestamps")
+            .arg("--png")
+            .arg("test_timestamps.png")
+            .assert()
+            .success();
+
+        let output = qrscan()
+            .arg("test_timestamps.png")
+            .arg("--timestamps")
+            .arg("--utc")
             .unwrap()
+            .stdout;
+        let output = String::from_utf8(output).unwrap();
+        let (timestamp, content) = output.split_once(' ').unwrap();
+        assert_eq!(content, "foo timestamps\n");
+        assert_eq!(timestamp.len(), "2023-01-01T00:00:00.000Z".len());
+        assert!(timestamp.ends_with('Z'));
+
+        let output = qrscan()
+            .arg("test_timestamps.png")
+            .arg("--timestamps")
+            .arg("--json")
+            .unwrap()
+            .stdout;
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["content"], "foo timestamps");
+        assert!(json["timestamp"].as_str().unwrap().contains('T'));
+
+        std::fs::remove_file("test_timestamps.png").unwrap();
+
+        qrscan().arg("--utc").assert().code(2);
+    }
+
+    #[test]
+    fn test_history() {
+        let path = std::path::Path::new("test_history.txt");
+        let _ = std::fs::remove_file(path);
+
+        let mut history = super::History::load(path, false).unwrap();
+        assert!(history.insert(b"foo").unwrap());
+        assert!(history.insert(b"bar\nbaz").unwrap());
+        assert!(!history.insert(b"foo").unwrap());
+
+        let mut history = super::History::load(path, false).unwrap();
+        assert!(!history.insert(b"bar\nbaz").unwrap());
+        assert!(history.insert(b"qux").unwrap());
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 3);
+
+        let mut history = super::History::load(path, true).unwrap();
+        assert!(history.insert(b"foo").unwrap());
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "Zm9v\n");
+
+        std::fs::remove_file(path).unwrap();
+
+        qrscan()
+            .arg("--dedup-file")
+            .arg("test_history.txt")
             .assert()
-            .success()
-            .stdout("foo png\n");
+            .code(2);
     }
 
     #[test]
-    fn test_scan_no_content() {
-        let file = TestFile::new("scan_no_content", "png");
+    fn test_size() {
         qrscan()
-            .arg(&file.path)
-            .arg("-n")
+            .arg("--encode")
+            .arg("foo size")
+            .arg("--size")
+            .arg("512")
+            .arg("--png")
+            .arg("test_size.png")
+            .assert()
+            .success();
+
+        let image = image::open("test_size.png").unwrap();
+        assert_eq!((image.width(), image.height()), (512, 512));
+
+        qrscan()
+            .arg("test_size.png")
             .assert()
             .success()
-            .stdout("");
+            .stdout("foo size\n");
+
+        std::fs::remove_file("test_size.png").unwrap();
 
         qrscan()
-            .arg(&file.path)
-            .arg("--no-content")
+            .arg("--encode")
+            .arg("foo")
+            .arg("--size")
+            .arg("20")
+            .arg("--png")
+            .arg("test_size_small.png")
+            .assert()
+            .failure()
+            .stderr(
+                "error: qrscan: size 20 is too small for the 29 modules of the code\n",
+            );
+    }
+
+    #[test]
+    fn test_style() {
+        for style in ["dots", "rounded"] {
+            let out = format!("test_style_{style}.png");
+
+            qrscan()
+                .arg("--encode")
+                .arg("foo style")
+                .arg("--style")
+                .arg(style)
+                .arg("--png")
+                .arg(&out)
+                .assert()
+                .success();
+
+            qrscan().arg(&out).assert().success().stdout("foo style\n");
+
+            std::fs::remove_file(&out).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fg_gradient() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo gradient")
+            .arg("--fg-gradient")
+            .arg("#f00, #00f, 45")
+            .arg("--png")
+            .arg("test_fg_gradient.png")
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("test_fg_gradient.png")
             .assert()
             .success()
-            .stdout("");
+            .stdout("foo gradient\n");
+
+        std::fs::remove_file("test_fg_gradient.png").unwrap();
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--fg-gradient")
+            .arg("#000,#ff0,90")
+            .arg("--png")
+            .arg("-")
+            .assert()
+            .failure()
+            .code(1)
+            .stderr("error: qrscan: the gradient color #ffff00 is too close to the background color #ffffff to scan\n");
+
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--fg-gradient")
+            .arg("#000,#00f")
+            .assert()
+            .failure()
+            .code(2);
     }
 
     #[test]
-    fn test_export_files() {
-        let file = TestFile::new("export_files", "png");
+    fn test_color_space() {
+        let export = |color_space: &str| {
+            qrscan()
+                .arg("--encode")
+                .arg("foo color space")
+                .arg("--fg")
+                .arg("#555")
+                .arg("--color-space")
+                .arg(color_space)
+                .arg("--png")
+                .arg("-")
+                .output()
+                .unwrap()
+                .stdout
+        };
+        let srgb = export("srgb");
+        let linear = export("linear");
+        assert_ne!(srgb, linear);
+
+        for png in [srgb, linear] {
+            qrscan()
+                .arg("-")
+                .write_stdin(png)
+                .assert()
+                .success()
+                .stdout("foo color space\n");
+        }
+
         qrscan()
-            .arg(&file.path)
-            .arg("--ascii")
-            .arg("test.ascii")
-            .arg("--svg")
-            .arg("test.svg")
-            .arg("--jpeg")
-            .arg("test.jpeg")
+            .arg("--encode")
+            .arg("foo")
+            .arg("--color-space")
+            .arg("cmyk")
+            .assert()
+            .failure()
+            .code(2);
+    }
+
+    #[test]
+    fn test_ec_level() {
+        qrscan()
+            .arg("--encode")
+            .arg("foo")
+            .arg("--ec-level")
+            .arg("H")
             .arg("--png")
-            .arg("test.png")
+            .arg("test_ec_level.png")
+            .assert()
+            .success();
+
+        qrscan()
+            .arg("test_ec_level.png")
+            .arg("--metadata")
+            .arg("--no-content")
             .assert()
             .success()
-            .stdout("foo png\n");
+            .stdout("Version: 1\nGrid Size: 21\nEC Level: 2\nMask: 4\nCapacity Bytes: 7\nErrors Corrected: 0 of 8\nBounds: 32,32 208,32 208,208 32,208\nContent Bytes: 3\nType: text\n");
 
-        assert!(PathBuf::from("test.ascii").exists());
-        assert!(PathBuf::from("test.svg").exists());
-        assert!(PathBuf::from("test.jpeg").exists());
-        assert!(PathBuf::from("test.png").exists());
+        std::fs::remove_file("test_ec_level.png").unwrap();
+    }
 
-        std::fs::remove_file("test.ascii").unwrap();
-        std::fs::remove_file("test.svg").unwrap();
-        std::fs::remove_file("test.jpeg").unwrap();
-        std::fs::remove_file("test.png").unwrap();
+    #[test]
+    fn test_err_not_found() {
+        image::RgbImage::from_pixel(100, 100, image::Rgb([255, 255, 255]))
+            .save("test_err_not_found.png")
+            .unwrap();
+
+        qrscan()
+            .arg("test_err_not_found.png")
+            .assert()
+            .failure()
+            .code(5)
+            .stderr("error: qrscan: test_err_not_found.png: no QR code found\n");
+
+        std::fs::remove_file("test_err_not_found.png").unwrap();
+    }
+
+    #[test]
+    fn test_err_undecodable() {
+        let opts = qrscan::RenderOptions {
+            module_size: 1,
+            ..Default::default()
+        };
+        let mut image = qrscan::build_binary_image(b"foo", &opts).unwrap();
+
+        // Flip the data modules in the bottom right, away from the finder patterns
+        // and the format information
+        for y in 4 + 9..4 + 21 {
+            for x in 4 + 9..4 + 21 {
+                let pixel = image.get_pixel_mut(x, y);
+                pixel.0[..3].iter_mut().for_each(|c| *c = u8::MAX - *c);
+            }
+        }
+        image.save("test_err_undecodable.png").unwrap();
+
+        let output = qrscan()
+            .arg("test_err_undecodable.png")
+            .arg("--min-size")
+            .arg("200")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert_eq!(output.status.code(), Some(6));
+        assert!(stderr.starts_with(
+            "error: qrscan: test_err_undecodable.png: found a QR code but failed to decode it: "
+        ));
+
+        std::fs::remove_file("test_err_undecodable.png").unwrap();
     }
 
     #[test]
     fn test_err_1() {
-        qrscan().arg("-").assert().failure().code(1);
+        qrscan().arg("-").assert().failure().code(8);
     }
 
     #[test]
@@ -497,4 +6967,9 @@ mod tests {
             .failure()
             .code(3);
     }
+
+    #[test]
+    fn test_err_8() {
+        qrscan().arg("-").assert().failure().code(8);
+    }
 }
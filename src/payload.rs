@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+
+/// The kind of data a QR code holds, detected from its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// An `http://` or `https://` URL.
+    Url,
+
+    /// A `mailto:` address.
+    Email,
+
+    /// A `tel:` phone number.
+    Phone,
+
+    /// A `geo:` location.
+    Geo,
+
+    /// A contact card, starting with `BEGIN:VCARD`.
+    VCard,
+
+    /// WiFi credentials, starting with `WIFI:`.
+    Wifi,
+
+    /// Anything else.
+    Text,
+}
+
+impl ContentType {
+    /// The lowercase name of the type, e.g. `url`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Url => "url",
+            Self::Email => "email",
+            Self::Phone => "phone",
+            Self::Geo => "geo",
+            Self::VCard => "vcard",
+            Self::Wifi => "wifi",
+            Self::Text => "text",
+        }
+    }
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Detect the type of the text from its scheme, ignoring case.
+pub fn classify(text: &str) -> ContentType {
+    let prefixes = [
+        ("http://", ContentType::Url),
+        ("https://", ContentType::Url),
+        ("mailto:", ContentType::Email),
+        ("tel:", ContentType::Phone),
+        ("geo:", ContentType::Geo),
+        ("BEGIN:VCARD", ContentType::VCard),
+        ("WIFI:", ContentType::Wifi),
+    ];
+
+    let text = text.trim_start();
+    prefixes
+        .into_iter()
+        .find(|(prefix, _)| {
+            text.get(..prefix.len())
+                .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+        })
+        .map(|(_, typ)| typ)
+        .unwrap_or(ContentType::Text)
+}
+
+/// The percent-decoded URL, or `mailto:`, `tel:` or `geo:` URI, like
+/// `https://example.com/a b` for `https://example.com/a%20b`. Returns `None` for the
+/// other types of text, which are left as they are. Invalid UTF-8 sequences are
+/// replaced.
+pub fn url_decode(text: &str) -> Option<Cow<'_, str>> {
+    match classify(text) {
+        ContentType::Url
+        | ContentType::Email
+        | ContentType::Phone
+        | ContentType::Geo => {
+            Some(percent_encoding::percent_decode_str(text).decode_utf8_lossy())
+        }
+        _ => None,
+    }
+}
+
+/// The WiFi network credentials in a `WIFI:` payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiNetwork {
+    /// The network name.
+    pub ssid: String,
+
+    /// The authentication type, e.g. `WPA` or `WEP`, or `nopass` for open networks.
+    pub auth: String,
+
+    /// The password, if any.
+    pub password: Option<String>,
+
+    /// Whether the network does not broadcast its SSID.
+    pub hidden: bool,
+}
+
+/// Parse a payload like `WIFI:T:WPA;S:ssid;P:password;;`.
+///
+/// Special characters in the values (`\`, `;`, `,`, `:` and `"`) are expected to be
+/// escaped with a backslash. Returns `None` if the text is not a WiFi payload or has
+/// no SSID.
+pub fn parse_wifi(text: &str) -> Option<WifiNetwork> {
+    let text = text.trim_end().strip_prefix("WIFI:")?;
+
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next()),
+            ';' => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    let mut ssid = None;
+    let mut auth = None;
+    let mut password = None;
+    let mut hidden = false;
+
+    for (key, value) in fields.into_iter().filter_map(split_field) {
+        match key.as_str() {
+            "S" => ssid = Some(value),
+            "T" if !value.is_empty() => auth = Some(value),
+            "P" if !value.is_empty() => password = Some(value),
+            "H" => hidden = value.eq_ignore_ascii_case("true"),
+            _ => {}
+        }
+    }
+
+    Some(WifiNetwork {
+        ssid: ssid?,
+        auth: auth.unwrap_or_else(|| "nopass".into()),
+        password,
+        hidden,
+    })
+}
+
+fn split_field(field: String) -> Option<(String, String)> {
+    let (key, value) = field.split_once(':')?;
+    Some((key.to_ascii_uppercase(), value.into()))
+}
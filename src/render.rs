@@ -0,0 +1,871 @@
+use crate::append::encode_part;
+use crate::StructuredAppend;
+use anyhow::bail;
+use anyhow::Result;
+use csscolorparser::Color;
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::gif::GifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::codecs::webp::WebPQuality;
+use image::imageops::FilterType;
+use image::ColorType;
+use image::DynamicImage;
+use image::EncodableLayout;
+use image::Frame;
+use image::ImageBuffer;
+use image::ImageEncoder;
+use image::Rgba;
+use image::RgbaImage;
+use qrencode::bits::Bits;
+use qrencode::canvas::Canvas;
+use qrencode::canvas::MaskPattern;
+use qrencode::render::svg;
+use qrencode::render::unicode::Dense1x2;
+use qrencode::render::unicode::Dense1x2::Dark;
+use qrencode::render::unicode::Dense1x2::Light;
+use qrencode::render::Pixel;
+use qrencode::render::Renderer;
+use qrencode::EcLevel;
+use qrencode::QrCode;
+use qrencode::Version;
+use std::fmt::Write;
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+
+/// The maximum number of pixels per module, to avoid gigantic allocations.
+pub const MAX_MODULE_SIZE: u32 = 32;
+
+/// The maximum width and height of raster images set with [`RenderOptions::size`].
+pub const MAX_IMAGE_SIZE: u32 = 8192;
+
+/// A linear gradient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// The color at the start.
+    pub start: Color,
+
+    /// The color at the end.
+    pub end: Color,
+
+    /// The direction in degrees, clockwise from left to right.
+    pub angle: f64,
+}
+
+impl Gradient {
+    /// The color at the given position in an image of the given size.
+    fn color_at(&self, x: u32, y: u32, width: u32, height: u32) -> Color {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let (dx, dy) = (
+            x as f64 - width as f64 / 2.0,
+            y as f64 - height as f64 / 2.0,
+        );
+
+        // The distance along the direction, from 0 at the start to 1 at the end
+        let length = width as f64 * cos.abs() + height as f64 * sin.abs();
+        let t = (dx * cos + dy * sin) / length + 0.5;
+
+        self.start.interpolate_rgb(&self.end, t.clamp(0.0, 1.0))
+    }
+}
+
+/// How the dark modules are drawn in raster images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModuleStyle {
+    /// Squares.
+    #[default]
+    Square,
+
+    /// Circles.
+    Dots,
+
+    /// Squares with rounded corners.
+    Rounded,
+}
+
+impl ModuleStyle {
+    /// Whether the pixel at the given position inside a module of the given size is
+    /// drawn.
+    fn covers(self, x: u32, y: u32, size: u32) -> bool {
+        let half = size as f64 / 2.0;
+        let radius = match self {
+            Self::Square => 0.0,
+            Self::Dots => half,
+            Self::Rounded => size as f64 / 3.0,
+        };
+
+        // The distance from the square inset by the corner radius
+        let dx = ((x as f64 + 0.5 - half).abs() - (half - radius)).max(0.0);
+        let dy = ((y as f64 + 0.5 - half).abs() - (half - radius)).max(0.0);
+
+        dx * dx + dy * dy <= radius * radius
+    }
+}
+
+/// How the colors are converted to the pixels of raster images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Written as is, so the pixels match the CSS colors.
+    #[default]
+    Srgb,
+
+    /// Converted to linear light, which darkens the midtones, as qrscan did before.
+    Linear,
+}
+
+impl ColorSpace {
+    /// The color as RGBA bytes.
+    fn rgba8(self, color: &Color) -> [u8; 4] {
+        match self {
+            Self::Srgb => color.to_rgba8(),
+            Self::Linear => {
+                let (r, g, b, a) = color.to_linear_rgba_u8();
+                [r, g, b, a]
+            }
+        }
+    }
+}
+
+/// Options for rendering a QR code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    /// The color of the dark modules, written as is in sRGB, unless raster images
+    /// are in the linear color space.
+    pub dark: Color,
+
+    /// The gradient to fill the dark modules with in raster images, instead of the
+    /// dark color.
+    pub gradient: Option<Gradient>,
+
+    /// The color of the light modules, written as is in sRGB, unless raster images
+    /// are in the linear color space.
+    pub light: Color,
+
+    /// How the colors are converted to the pixels of raster images. Vector images
+    /// are always in sRGB.
+    pub color_space: ColorSpace,
+
+    /// The width of the quiet zone around the code, in modules (the standard is 4).
+    pub quiet_zone: u32,
+
+    /// The number of pixels per module in raster images, capped at
+    /// [`MAX_MODULE_SIZE`].
+    pub module_size: u32,
+
+    /// The exact width and height of raster images in pixels, capped at
+    /// [`MAX_IMAGE_SIZE`]. Overrides `module_size` with the largest whole number of
+    /// pixels per module that fits, and widens the quiet zone by the remainder.
+    pub size: Option<u32>,
+
+    /// The resolution written to PNG images, in dots per inch. No resolution is
+    /// written if unset.
+    pub dpi: Option<u32>,
+
+    /// The quality of JPEG images, from 1 to 100. Low qualities blur the modules
+    /// until the code is unscannable.
+    pub jpeg_quality: u8,
+
+    /// The error correction level.
+    pub ec_level: EcLevel,
+
+    /// The smallest version to encode the content in, from 1 to 40. Higher versions
+    /// have more modules.
+    pub min_version: i16,
+
+    /// The largest version to encode the content in, from 1 to 40. Fails if the
+    /// content does not fit.
+    pub max_version: i16,
+
+    /// The mask pattern, numbered as in the format information (0 to 7, or 0 to 3 for
+    /// Micro QR codes), instead of the one that scans best. Fixing it makes the
+    /// generated codes reproducible across encoder versions.
+    pub mask: Option<u8>,
+
+    /// Encode the content as a Micro QR code, of the smallest of its 4 versions that
+    /// fits, instead of the version range. Micro QR codes do not support the highest
+    /// error correction level.
+    pub micro: bool,
+
+    /// The position of the code in a structured append sequence, to encode a part
+    /// of a content split with [`split_content`](crate::split_content).
+    pub structured_append: Option<StructuredAppend>,
+
+    /// How the dark modules are drawn in raster images. The finder patterns stay
+    /// square.
+    pub style: ModuleStyle,
+
+    /// An image to draw over the center of raster images. The highest error
+    /// correction level is used then, to make up for the hidden modules.
+    pub logo: Option<DynamicImage>,
+
+    /// The width of the logo in percent of the code's width, capped to keep it clear
+    /// of the finder patterns.
+    pub logo_size: u32,
+}
+
+impl RenderOptions {
+    /// The error correction level of raster images, the highest with a logo.
+    pub(crate) fn raster_ec_level(&self) -> EcLevel {
+        if self.logo.is_some() {
+            EcLevel::H
+        } else {
+            self.ec_level
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            dark: Color::new(0.0, 0.0, 0.0, 1.0),
+            gradient: None,
+            light: Color::new(1.0, 1.0, 1.0, 1.0),
+            color_space: ColorSpace::Srgb,
+            quiet_zone: 4,
+            module_size: 8,
+            size: None,
+            dpi: None,
+            jpeg_quality: 95,
+            ec_level: EcLevel::M,
+            min_version: 1,
+            max_version: 40,
+            mask: None,
+            micro: false,
+            structured_append: None,
+            style: ModuleStyle::Square,
+            logo: None,
+            logo_size: 20,
+        }
+    }
+}
+
+/// Mask the canvas with the pattern numbered as in the format information, or with the
+/// one that scans best.
+fn apply_mask(mut canvas: Canvas, version: Version, mask: Option<u8>) -> Result<Canvas> {
+    let Some(mask) = mask else {
+        return Ok(canvas.apply_best_mask());
+    };
+
+    let patterns: &[MaskPattern] = if version.is_micro() {
+        &[
+            MaskPattern::HorizontalLines,
+            MaskPattern::LargeCheckerboard,
+            MaskPattern::Diamonds,
+            MaskPattern::Meadow,
+        ]
+    } else {
+        &[
+            MaskPattern::Checkerboard,
+            MaskPattern::HorizontalLines,
+            MaskPattern::VerticalLines,
+            MaskPattern::DiagonalLines,
+            MaskPattern::LargeCheckerboard,
+            MaskPattern::Fields,
+            MaskPattern::Diamonds,
+            MaskPattern::Meadow,
+        ]
+    };
+    let Some(&pattern) = patterns.get(mask as usize) else {
+        let kind = if version.is_micro() { "Micro QR" } else { "QR" };
+        bail!(
+            "invalid mask {mask}, {kind} codes have masks 0 to {}",
+            patterns.len() - 1
+        );
+    };
+
+    canvas.apply_mask(pattern);
+    Ok(canvas)
+}
+
+/// The modules of the encoded content, and the number of modules per side.
+struct Modules {
+    colors: Vec<qrencode::Color>,
+    width: usize,
+    micro: bool,
+}
+
+impl Modules {
+    /// Encode the content in the smallest version allowed by the options.
+    fn new(content: &[u8], ec_level: EcLevel, opts: &RenderOptions) -> Result<Self> {
+        if opts.micro {
+            return Self::micro(content, ec_level, opts.mask);
+        }
+
+        let (min, max) = (opts.min_version, opts.max_version);
+        if !(1..=40).contains(&min) || !(1..=40).contains(&max) || min > max {
+            bail!("invalid version range {min} to {max}");
+        }
+
+        if let Some(part) = opts.structured_append {
+            return Self::with_structured_append(
+                content,
+                part,
+                ec_level,
+                min..=max,
+                opts.mask,
+            );
+        }
+
+        let mut code = QrCode::with_error_correction_level(content, ec_level)?;
+        match code.version() {
+            Version::Normal(version) if version > max => {
+                bail!("the content needs version {version}, above the maximum version {max}")
+            }
+            Version::Normal(version) if version < min => {
+                code = QrCode::with_version(content, Version::Normal(min), ec_level)?;
+            }
+            _ => {}
+        }
+
+        if let Some(mask) = opts.mask {
+            return Self::with_mask(content, code.version(), ec_level, mask);
+        }
+
+        let width = code.width();
+        Ok(Self {
+            colors: code.into_colors(),
+            width,
+            micro: false,
+        })
+    }
+
+    /// Encode the content in the smallest Micro QR code version that fits.
+    fn micro(content: &[u8], ec_level: EcLevel, mask: Option<u8>) -> Result<Self> {
+        for version in 1..=4 {
+            let Ok(code) =
+                QrCode::with_version(content, Version::Micro(version), ec_level)
+            else {
+                continue;
+            };
+
+            if let Some(mask) = mask {
+                return Self::with_mask(
+                    content,
+                    Version::Micro(version),
+                    ec_level,
+                    mask,
+                );
+            }
+
+            let width = code.width();
+            return Ok(Self {
+                colors: code.into_colors(),
+                width,
+                micro: true,
+            });
+        }
+
+        bail!("the content does not fit in a Micro QR code with error correction level {ec_level:?}")
+    }
+
+    /// Encode the part of a structured append sequence in the smallest version of the
+    /// range that fits.
+    fn with_structured_append(
+        content: &[u8],
+        part: StructuredAppend,
+        ec_level: EcLevel,
+        versions: RangeInclusive<i16>,
+        mask: Option<u8>,
+    ) -> Result<Self> {
+        let max = *versions.end();
+        for version in versions {
+            let Some(data) = encode_part(content, part, version, ec_level)? else {
+                continue;
+            };
+
+            let version = Version::Normal(version);
+            let (data, ec) =
+                qrencode::ec::construct_codewords(&data, version, ec_level)?;
+            let mut canvas = Canvas::new(version, ec_level);
+            canvas.draw_all_functional_patterns();
+            canvas.draw_data(&data, &ec);
+
+            return Ok(Self {
+                colors: apply_mask(canvas, version, mask)?.into_colors(),
+                width: version.width() as usize,
+                micro: false,
+            });
+        }
+
+        bail!("the part does not fit in version {max}")
+    }
+
+    /// Encode the content in the version, masked with the given pattern.
+    fn with_mask(
+        content: &[u8],
+        version: Version,
+        ec_level: EcLevel,
+        mask: u8,
+    ) -> Result<Self> {
+        let mut bits = Bits::new(version);
+        bits.push_optimal_data(content)?;
+        bits.push_terminator(ec_level)?;
+
+        let (data, ec) =
+            qrencode::ec::construct_codewords(&bits.into_bytes(), version, ec_level)?;
+        let mut canvas = Canvas::new(version, ec_level);
+        canvas.draw_all_functional_patterns();
+        canvas.draw_data(&data, &ec);
+
+        Ok(Self {
+            colors: apply_mask(canvas, version, Some(mask))?.into_colors(),
+            width: version.width() as usize,
+            micro: version.is_micro(),
+        })
+    }
+
+    /// A renderer with the quiet zone of the given width.
+    fn render<P: Pixel>(&self, quiet_zone: u32) -> Renderer<'_, P> {
+        Renderer::new(&self.colors, self.width, quiet_zone)
+    }
+
+    /// The side of a vector image of the modules, and the rectangles to fill for its
+    /// horizontal runs of dark modules over the background. Each rectangle is a module
+    /// high, as its left and bottom from the bottom left corner, like in PDF and
+    /// PostScript, and its width.
+    fn dark_runs(
+        &self,
+        quiet_zone: u32,
+        module_size: u32,
+    ) -> (u32, Vec<(u32, u32, u32)>) {
+        let size = (self.width as u32 + 2 * quiet_zone) * module_size;
+        let mut runs = vec![];
+        for (y, row) in self.colors.chunks(self.width).enumerate() {
+            let bottom = size - (quiet_zone + y as u32 + 1) * module_size;
+            let mut start = None;
+            for (x, module) in row.iter().chain([&qrencode::Color::Light]).enumerate() {
+                match (module, start) {
+                    (qrencode::Color::Dark, None) => start = Some(x),
+                    (qrencode::Color::Light, Some(first)) => {
+                        let left = (quiet_zone + first as u32) * module_size;
+                        let width = (x - first) as u32 * module_size;
+                        runs.push((left, bottom, width));
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        (size, runs)
+    }
+
+    /// Whether the module at the given position is part of a finder pattern.
+    fn in_finder_pattern(&self, x: usize, y: usize) -> bool {
+        if self.micro {
+            return x < 7 && y < 7;
+        }
+
+        let far = self.width.saturating_sub(7);
+        (y < 7 && (x < 7 || x >= far)) || (x < 7 && y >= far)
+    }
+
+    /// Draw the modules in the given style, with the quiet zone of the given width.
+    fn draw(
+        &self,
+        style: ModuleStyle,
+        quiet_zone: u32,
+        module_size: u32,
+        dark: Rgba<u8>,
+        light: Rgba<u8>,
+    ) -> RgbaImage {
+        let size = (self.width as u32 + 2 * quiet_zone) * module_size;
+        let mut img = RgbaImage::from_pixel(size, size, light);
+
+        for (i, color) in self.colors.iter().enumerate() {
+            if *color != qrencode::Color::Dark {
+                continue;
+            }
+
+            let (x, y) = (i % self.width, i / self.width);
+            let style = if self.in_finder_pattern(x, y) {
+                ModuleStyle::Square
+            } else {
+                style
+            };
+
+            let left = (x as u32 + quiet_zone) * module_size;
+            let top = (y as u32 + quiet_zone) * module_size;
+            for dy in 0..module_size {
+                for dx in 0..module_size {
+                    if style.covers(dx, dy, module_size) {
+                        img.put_pixel(left + dx, top + dy, dark);
+                    }
+                }
+            }
+        }
+
+        img
+    }
+}
+
+/// Render the content as an RGBA image.
+pub fn build_binary_image(
+    content: &[u8],
+    opts: &RenderOptions,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let [dr, dg, db, da] = opts.color_space.rgba8(&opts.dark);
+    let [lr, lg, lb, la] = opts.color_space.rgba8(&opts.light);
+
+    let modules = Modules::new(content, opts.raster_ec_level(), opts)?;
+    let size = opts.size.map(|size| size.min(MAX_IMAGE_SIZE));
+    let module_size = match size {
+        Some(size) => {
+            let modules = modules.width as u32 + 2 * opts.quiet_zone;
+            if size < modules {
+                bail!("size {size} is too small for the {modules} modules of the code");
+            }
+            size / modules
+        }
+        None => opts.module_size.min(MAX_MODULE_SIZE),
+    };
+
+    let mut img = match opts.style {
+        ModuleStyle::Square => modules
+            .render::<Rgba<u8>>(opts.quiet_zone)
+            .module_dimensions(module_size, module_size)
+            .dark_color(Rgba([dr, dg, db, da]))
+            .light_color(Rgba([lr, lg, lb, la]))
+            .build(),
+        style => modules.draw(
+            style,
+            opts.quiet_zone,
+            module_size,
+            Rgba([dr, dg, db, da]),
+            Rgba([lr, lg, lb, la]),
+        ),
+    };
+
+    if let Some(gradient) = opts.gradient.as_ref() {
+        let dark = Rgba([dr, dg, db, da]);
+        let (width, height) = img.dimensions();
+
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if *pixel == dark {
+                let color = gradient.color_at(x, y, width, height);
+                *pixel = Rgba(opts.color_space.rgba8(&color));
+            }
+        }
+    }
+
+    if let Some(logo) = opts.logo.as_ref() {
+        // The finder patterns and their separators take 8 modules in each corner
+        let width = modules.width as u32;
+        let logo_modules = (width * opts.logo_size / 100).min(width.saturating_sub(16));
+        let light = Rgba([lr, lg, lb, la]);
+        draw_logo(&mut img, logo, logo_modules * module_size, light);
+    }
+
+    if let Some(size) = size.filter(|&size| size != img.width()) {
+        // Whole pixels per module keep the edges crisp, so pad the rest instead
+        let mut padded = RgbaImage::from_pixel(size, size, Rgba([lr, lg, lb, la]));
+        let offset = ((size - img.width()) / 2) as i64;
+        image::imageops::replace(&mut padded, &img, offset, offset);
+        img = padded;
+    }
+
+    Ok(img)
+}
+
+/// Draw the logo, scaled to fit in a square of the given size, over the center of the
+/// image on the light color.
+fn draw_logo(image: &mut RgbaImage, logo: &DynamicImage, size: u32, light: Rgba<u8>) {
+    if size == 0 {
+        return;
+    }
+
+    let logo = logo.resize(size, size, FilterType::Lanczos3).to_rgba8();
+    let x = (image.width() - logo.width()) / 2;
+    let y = (image.height() - logo.height()) / 2;
+
+    for dy in 0..logo.height() {
+        for dx in 0..logo.width() {
+            image.put_pixel(x + dx, y + dy, light);
+        }
+    }
+    image::imageops::overlay(image, &logo, x.into(), y.into());
+}
+
+/// Encode the content as a PNG image.
+pub fn encode_to_png(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let image = build_binary_image(content, opts)?;
+    let bytes = image.as_bytes();
+
+    let mut result: Vec<u8> = Default::default();
+    if let Some(dpi) = opts.dpi {
+        let mut encoder = png::Encoder::new(&mut result, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        // PNG stores the resolution in pixels per meter
+        let ppu = (dpi as f64 / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: ppu,
+            yppu: ppu,
+            unit: png::Unit::Meter,
+        }));
+
+        encoder.write_header()?.write_image_data(bytes)?;
+    } else {
+        let encoder = PngEncoder::new(&mut result);
+        encoder.write_image(bytes, image.width(), image.height(), ColorType::Rgba8)?;
+    }
+    Ok(result)
+}
+
+/// The image composited over a white background, for what ignores the alpha channel.
+pub(crate) fn on_white(image: &RgbaImage) -> RgbaImage {
+    let mut image = image.clone();
+    for Rgba([r, g, b, a]) in image.pixels_mut() {
+        let alpha = *a as u32;
+        for channel in [r, g, b] {
+            *channel = ((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+        *a = 255;
+    }
+    image
+}
+
+/// Encode the content as a JPEG image, on a white background as it has no
+/// transparency.
+pub fn encode_to_jpeg(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let image = on_white(&build_binary_image(content, opts)?);
+    let bytes = image.as_bytes();
+
+    let mut result: Vec<u8> = Default::default();
+    let mut encoder = JpegEncoder::new_with_quality(&mut result, opts.jpeg_quality);
+    encoder.encode(bytes, image.width(), image.height(), ColorType::Rgba8)?;
+    Ok(result)
+}
+
+/// Encode the content as a lossless WebP image.
+pub fn encode_to_webp(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let image = build_binary_image(content, opts)?;
+    let bytes = image.as_bytes();
+
+    let mut result: Vec<u8> = Default::default();
+    let encoder = WebPEncoder::new_with_quality(&mut result, WebPQuality::lossless());
+    encoder.encode(bytes, image.width(), image.height(), ColorType::Rgba8)?;
+    Ok(result)
+}
+
+/// Encode the content as a BMP image.
+pub fn encode_to_bmp(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let image = build_binary_image(content, opts)?;
+    let bytes = image.as_bytes();
+
+    let mut result: Vec<u8> = Default::default();
+    let mut encoder = BmpEncoder::new(&mut result);
+    encoder.encode(bytes, image.width(), image.height(), ColorType::Rgba8)?;
+    Ok(result)
+}
+
+/// Encode the content as a TIFF image.
+pub fn encode_to_tiff(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let image = build_binary_image(content, opts)?;
+    let bytes = image.as_bytes();
+
+    let mut result = Cursor::new(Vec::new());
+    let encoder = TiffEncoder::new(&mut result);
+    encoder.encode(bytes, image.width(), image.height(), ColorType::Rgba8)?;
+    Ok(result.into_inner())
+}
+
+/// Encode the content as a single frame GIF image.
+pub fn encode_to_gif(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let image = build_binary_image(content, opts)?;
+    encode_gif_frames([Frame::new(image)])
+}
+
+/// Encode the frames as a GIF image, animated if there are more than one.
+fn encode_gif_frames(frames: impl IntoIterator<Item = Frame>) -> Result<Vec<u8>> {
+    let mut result: Vec<u8> = Default::default();
+    let mut encoder = GifEncoder::new(&mut result);
+    encoder.encode_frames(frames)?;
+    drop(encoder);
+    Ok(result)
+}
+
+/// Encode the content as its modules row by row, `true` for the dark ones, without
+/// the quiet zone.
+pub fn encode_to_matrix(content: &[u8], opts: &RenderOptions) -> Result<Vec<Vec<bool>>> {
+    let modules = Modules::new(content, opts.ec_level, opts)?;
+    let rows = modules
+        .colors
+        .chunks(modules.width)
+        .map(|row| {
+            row.iter()
+                .map(|color| *color == qrencode::Color::Dark)
+                .collect()
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// Encode the content as an SVG image.
+pub fn encode_to_svg(content: &[u8], opts: &RenderOptions) -> Result<String> {
+    let dark = opts.dark.to_hex_string();
+    let light = opts.light.to_hex_string();
+
+    let image = Modules::new(content, opts.ec_level, opts)?
+        .render(opts.quiet_zone)
+        .dark_color(svg::Color(&dark))
+        .light_color(svg::Color(&light))
+        .build();
+    Ok(image)
+}
+
+/// Encode the content as a single page vector PDF document, with `module_size`
+/// points per module.
+pub fn encode_to_pdf(content: &[u8], opts: &RenderOptions) -> Result<Vec<u8>> {
+    let modules = Modules::new(content, opts.ec_level, opts)?;
+    let module_size = opts.module_size.min(MAX_MODULE_SIZE);
+    let (size, runs) = modules.dark_runs(opts.quiet_zone, module_size);
+
+    let mut stream = String::new();
+    let Color { r, g, b, .. } = opts.light;
+    writeln!(stream, "{r:.3} {g:.3} {b:.3} rg 0 0 {size} {size} re f")?;
+    let Color { r, g, b, .. } = opts.dark;
+    writeln!(stream, "{r:.3} {g:.3} {b:.3} rg")?;
+    for (left, bottom, width) in runs {
+        writeln!(stream, "{left} {bottom} {width} {module_size} re")?;
+    }
+    stream.push_str("f\n");
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {size} {size}] /Contents 4 0 R >>"),
+        format!("<< /Length {} >>\nstream\n{stream}endstream", stream.len()),
+    ];
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        writeln!(pdf, "{} 0 obj\n{object}\nendobj", i + 1)?;
+    }
+
+    // The cross-reference table, pointing at each object
+    let xref = pdf.len();
+    writeln!(pdf, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1)?;
+    for offset in offsets {
+        writeln!(pdf, "{offset:010} 00000 n ")?;
+    }
+    writeln!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>",
+        objects.len() + 1
+    )?;
+    writeln!(pdf, "startxref\n{xref}\n%%EOF")?;
+
+    Ok(pdf.into_bytes())
+}
+
+/// Encode the content as an EPS vector image, with a point per pixel of the raster
+/// images.
+pub fn encode_to_eps(content: &[u8], opts: &RenderOptions) -> Result<String> {
+    let modules = Modules::new(content, opts.ec_level, opts)?;
+    let module_size = opts.module_size.min(MAX_MODULE_SIZE);
+    let (size, runs) = modules.dark_runs(opts.quiet_zone, module_size);
+
+    let mut eps = String::from("%!PS-Adobe-3.0 EPSF-3.0\n");
+    writeln!(eps, "%%BoundingBox: 0 0 {size} {size}")?;
+    writeln!(eps, "%%Creator: qrscan\n%%EndComments")?;
+
+    let Color { r, g, b, .. } = opts.light;
+    writeln!(
+        eps,
+        "{r:.3} {g:.3} {b:.3} setrgbcolor 0 0 {size} {size} rectfill"
+    )?;
+    let Color { r, g, b, .. } = opts.dark;
+    writeln!(eps, "{r:.3} {g:.3} {b:.3} setrgbcolor")?;
+    for (left, bottom, width) in runs {
+        writeln!(eps, "{left} {bottom} {width} {module_size} rectfill")?;
+    }
+    eps.push_str("showpage\n%%EOF\n");
+
+    Ok(eps)
+}
+
+/// Encode the content as ASCII text, using `#` for the dark modules.
+pub fn encode_to_ascii(content: &[u8], opts: &RenderOptions) -> Result<String> {
+    let image = Modules::new(content, opts.ec_level, opts)?
+        .render::<char>(opts.quiet_zone)
+        .module_dimensions(2, 1)
+        .dark_color('#')
+        .light_color(' ')
+        .build();
+    Ok(image)
+}
+
+/// Encode the content as braille patterns of 2x4 modules each, for printing dense
+/// codes on the terminal.
+///
+/// The dots are drawn for the light modules, like the unicode blocks, unless
+/// `invert` is set.
+pub fn encode_to_braille(
+    content: &[u8],
+    opts: &RenderOptions,
+    invert: bool,
+) -> Result<String> {
+    // The bits of the dots in each column of a braille pattern, from top to bottom
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+    let modules = Modules::new(content, opts.ec_level, opts)?;
+    let quiet_zone = opts.quiet_zone as usize;
+    let width = modules.width + 2 * quiet_zone;
+
+    let lit = |x: usize, y: usize| {
+        if x >= width || y >= width {
+            return false;
+        }
+        let dark = match (x.checked_sub(quiet_zone), y.checked_sub(quiet_zone)) {
+            (Some(x), Some(y)) if x < modules.width && y < modules.width => {
+                modules.colors[y * modules.width + x] == qrencode::Color::Dark
+            }
+            _ => false,
+        };
+        dark == invert
+    };
+
+    let mut text = String::new();
+    for row in 0..width.div_ceil(4) {
+        if row != 0 {
+            text.push('\n');
+        }
+        for col in 0..width.div_ceil(2) {
+            let mut pattern = 0x2800;
+            for (dx, column) in DOTS.iter().enumerate() {
+                for (dy, dot) in column.iter().enumerate() {
+                    if lit(col * 2 + dx, row * 4 + dy) {
+                        pattern |= dot;
+                    }
+                }
+            }
+            text.push(char::from_u32(pattern).unwrap_or(' '));
+        }
+    }
+
+    Ok(text)
+}
+
+/// Encode the content as unicode half blocks, for printing on the terminal.
+///
+/// Terminals usually print light text on a dark background, so the blocks are
+/// inverted unless `invert` is set.
+pub fn encode_to_unicode(
+    content: &[u8],
+    opts: &RenderOptions,
+    invert: bool,
+) -> Result<String> {
+    let (dark, light) = if invert { (Dark, Light) } else { (Light, Dark) };
+
+    let image = Modules::new(content, opts.ec_level, opts)?
+        .render::<Dense1x2>(opts.quiet_zone)
+        .dark_color(dark)
+        .light_color(light)
+        .build();
+    Ok(image)
+}
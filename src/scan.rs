@@ -0,0 +1,1141 @@
+use crate::append::byte_capacity;
+use crate::render::on_white;
+use crate::ScanError;
+use crate::StructuredAppend;
+use encoding_rs::Encoding;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use image::GrayImage;
+use image::Luma;
+use qrencode::EcLevel;
+use rxing::common::reedsolomon::get_predefined_genericgf;
+use rxing::common::reedsolomon::PredefinedGenericGF;
+use rxing::common::reedsolomon::ReedSolomonDecoder;
+use rxing::common::BitMatrix;
+use rxing::qrcode::decoder::BitMatrixParser;
+use rxing::qrcode::decoder::DataBlock;
+use rxing::BarcodeFormat;
+use rxing::DecodeHintType;
+use rxing::DecodeHintValue;
+use rxing::DecodingHintDictionary;
+use rxing::Exceptions;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The kind of a code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbology {
+    /// QR code.
+    Qr,
+
+    /// Micro QR code, with a single finder pattern, for tiny labels.
+    MicroQr,
+
+    /// Aztec code, common on transit tickets.
+    Aztec,
+
+    /// Data Matrix code, common on electronics labels.
+    DataMatrix,
+
+    /// PDF417 stacked barcode, common on IDs and boarding passes.
+    Pdf417,
+
+    /// EAN-13 barcode, common on retail products.
+    Ean13,
+
+    /// EAN-8 barcode, for small retail products.
+    Ean8,
+
+    /// UPC-A barcode, common on retail products in North America.
+    UpcA,
+
+    /// UPC-E barcode, for small retail products in North America.
+    UpcE,
+
+    /// Code 128 barcode, common in shipping and logistics.
+    Code128,
+
+    /// Code 39 barcode.
+    Code39,
+
+    /// Code 93 barcode.
+    Code93,
+
+    /// Codabar barcode.
+    Codabar,
+
+    /// Interleaved 2 of 5 barcode.
+    Itf,
+}
+
+impl Symbology {
+    /// All the symbologies, in the order they are tried.
+    pub const ALL: &'static [Self] = &[
+        Self::Qr,
+        Self::MicroQr,
+        Self::Aztec,
+        Self::DataMatrix,
+        Self::Pdf417,
+        Self::Ean13,
+        Self::Ean8,
+        Self::UpcA,
+        Self::UpcE,
+        Self::Code128,
+        Self::Code39,
+        Self::Code93,
+        Self::Codabar,
+        Self::Itf,
+    ];
+
+    /// The lowercase name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Qr => "qr",
+            Self::MicroQr => "microqr",
+            Self::Aztec => "aztec",
+            Self::DataMatrix => "datamatrix",
+            Self::Pdf417 => "pdf417",
+            Self::Ean13 => "ean13",
+            Self::Ean8 => "ean8",
+            Self::UpcA => "upca",
+            Self::UpcE => "upce",
+            Self::Code128 => "code128",
+            Self::Code39 => "code39",
+            Self::Code93 => "code93",
+            Self::Codabar => "codabar",
+            Self::Itf => "itf",
+        }
+    }
+
+    /// Whether it is a one-dimensional barcode. These are only looked for on demand,
+    /// as stray lines in photos are easily mistaken for them.
+    pub fn is_linear(self) -> bool {
+        !matches!(
+            self,
+            Self::Qr | Self::MicroQr | Self::Aztec | Self::DataMatrix | Self::Pdf417
+        )
+    }
+
+    fn format(self) -> BarcodeFormat {
+        match self {
+            Self::Qr => BarcodeFormat::QR_CODE,
+            Self::MicroQr => BarcodeFormat::MICRO_QR_CODE,
+            Self::Aztec => BarcodeFormat::AZTEC,
+            Self::DataMatrix => BarcodeFormat::DATA_MATRIX,
+            Self::Pdf417 => BarcodeFormat::PDF_417,
+            Self::Ean13 => BarcodeFormat::EAN_13,
+            Self::Ean8 => BarcodeFormat::EAN_8,
+            Self::UpcA => BarcodeFormat::UPC_A,
+            Self::UpcE => BarcodeFormat::UPC_E,
+            Self::Code128 => BarcodeFormat::CODE_128,
+            Self::Code39 => BarcodeFormat::CODE_39,
+            Self::Code93 => BarcodeFormat::CODE_93,
+            Self::Codabar => BarcodeFormat::CODABAR,
+            Self::Itf => BarcodeFormat::ITF,
+        }
+    }
+
+    fn from_format(format: &BarcodeFormat) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|symbology| symbology.format() == *format)
+    }
+}
+
+/// A decoded code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedCode {
+    /// The decoded payload. It is usually, but not necessarily, UTF-8 text.
+    pub content: Vec<u8>,
+
+    /// The kind of code. The version, error correction level and mask are only set
+    /// for QR codes, and 0 otherwise.
+    pub symbology: Symbology,
+
+    /// The character encoding of the content declared by the QR code, with an ECI
+    /// designator or implied by Kanji mode (Shift_JIS). UTF-8 is assumed if unset.
+    /// ISO-8859-1 is reported as its windows-1252 superset.
+    pub encoding: Option<&'static Encoding>,
+
+    /// The QR code version, from 1 to 40.
+    pub version: usize,
+
+    /// The error correction level, as encoded in the format information
+    /// (0: M, 1: L, 2: H, 3: Q).
+    pub ecc_level: u16,
+
+    /// The mask pattern, from 0 to 7.
+    pub mask: u16,
+
+    /// The corners of the code as `(x, y)` pixel positions in the scanned image,
+    /// clockwise from the top left corner of the code.
+    pub bounds: [(i32, i32); 4],
+
+    /// The position of the code in a structured append sequence, if the content is
+    /// split across several QR codes. See [`join_parts`](crate::join_parts).
+    pub structured_append: Option<StructuredAppend>,
+
+    /// The modules of a QR code row by row, `true` for the dark ones, as read from
+    /// the image. Empty for the other symbologies.
+    pub modules: Vec<Vec<bool>>,
+
+    /// How many errors the error correction of a QR code fixed. `None` for the other
+    /// symbologies, or if the codewords could not be read again to count them.
+    pub corrections: Option<Corrections>,
+}
+
+/// The number of codewords that error correction fixed, out of the most it can fix. A
+/// code that needed most of them fixed is likely damaged, and a few more errors may
+/// decode it to a wrong content.
+///
+/// Each error correction block of a code is corrected on its own, so a code of several
+/// blocks can have one block at its limit while the totals look fine. The block that
+/// needed the largest share of its correction is kept too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Corrections {
+    /// The number of codewords fixed.
+    pub errors: usize,
+
+    /// The most codewords that can be fixed, half of the error correction codewords.
+    pub max_errors: usize,
+
+    /// The number of codewords fixed in the block that needed the largest share of
+    /// its correction.
+    pub block_errors: usize,
+
+    /// The most codewords that can be fixed in that block.
+    pub block_max_errors: usize,
+}
+
+impl Corrections {
+    /// Whether a block needed more than half of the errors it can fix corrected.
+    pub fn is_damaged(&self) -> bool {
+        self.block_errors * 2 > self.block_max_errors
+    }
+}
+
+impl DecodedCode {
+    /// The number of modules on each side of the code.
+    pub fn grid_size(&self) -> usize {
+        self.version * 4 + 17
+    }
+
+    /// The number of bytes that fit in a QR code of its version and error correction
+    /// level, in one byte mode segment. `None` for the other symbologies.
+    pub fn capacity(&self) -> Option<usize> {
+        if self.symbology != Symbology::Qr {
+            return None;
+        }
+
+        let ec_level = match self.ecc_level {
+            0 => EcLevel::M,
+            1 => EcLevel::L,
+            2 => EcLevel::H,
+            _ => EcLevel::Q,
+        };
+        byte_capacity(self.version as i16, ec_level).ok()
+    }
+
+    /// The content as text, if it is valid UTF-8.
+    pub fn content_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.content).ok()
+    }
+
+    /// The content decoded from its declared encoding, or UTF-8, if it is valid.
+    pub fn text(&self) -> Option<Cow<'_, str>> {
+        self.encoding_or_utf8()
+            .decode_without_bom_handling_and_without_replacement(&self.content)
+    }
+
+    /// The content decoded from its declared encoding, or UTF-8, replacing the
+    /// malformed sequences.
+    pub fn text_lossy(&self) -> Cow<'_, str> {
+        self.encoding_or_utf8()
+            .decode_without_bom_handling(&self.content)
+            .0
+    }
+
+    fn encoding_or_utf8(&self) -> &'static Encoding {
+        self.encoding.unwrap_or(encoding_rs::UTF_8)
+    }
+}
+
+/// How to binarize the image before detecting the codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threshold {
+    /// Pixels brighter than the value become white, the others black.
+    Fixed(u8),
+
+    /// Pixels brighter than the mean of their neighborhood become white, the others
+    /// black. Helps with gradients and shadows.
+    Adaptive,
+}
+
+/// Options for scanning an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Whether to retry on the image rotated by 90, 180 and 270 degrees when no code
+    /// could be read.
+    pub rotate: bool,
+
+    /// The binarization to apply, instead of leaving it to the decoder.
+    pub threshold: Option<Threshold>,
+
+    /// Whether to invert the image before scanning, for light codes on a dark
+    /// background.
+    pub invert: bool,
+
+    /// Upscale the image by a whole factor, keeping the modules sharp, until its
+    /// smaller side has at least this many pixels. Left as is if its larger side would
+    /// not fit in a `u32`.
+    pub min_size: Option<u32>,
+
+    /// Downscale the image, unless it is upscaled to `min_size`, until its larger
+    /// side has at most this many pixels. Detecting codes in huge images is slow, and
+    /// rarely needs the full resolution.
+    pub max_dimension: Option<u32>,
+
+    /// The only kind of code to look for. If unset, the other two-dimensional kinds
+    /// are looked for when no QR code is detected.
+    pub symbology: Option<Symbology>,
+
+    /// The fewest modules per side of the QR codes to decode, from 21 (version 1).
+    /// Smaller detections are discarded as spurious.
+    pub min_modules: usize,
+
+    /// The most modules per side of the QR codes to decode, up to 177 (version 40).
+    /// Larger detections are discarded as spurious.
+    pub max_modules: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            rotate: true,
+            threshold: None,
+            invert: false,
+            min_size: None,
+            max_dimension: None,
+            symbology: None,
+            min_modules: 21,
+            max_modules: 177,
+        }
+    }
+}
+
+/// Detect and decode all the QR codes in the image, in the order they are found, or
+/// the codes of the other symbologies if there is no QR code.
+///
+/// Codes that fail to decode are skipped, unless none of them could be decoded, in
+/// which case the first error is returned as [`ScanError::DecodeFailed`]. Returns an
+/// empty list if no code was detected at all.
+pub fn scan_image(image: &DynamicImage) -> Result<Vec<DecodedCode>, ScanError> {
+    scan_image_with(image, &Default::default())
+}
+
+/// Same as [`scan_image`], with the given options.
+pub fn scan_image_with(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> Result<Vec<DecodedCode>, ScanError> {
+    let (codes, failed) = scan_image_failed(image, opts)?;
+    match failed.into_iter().next() {
+        Some(failed) if codes.is_empty() => Err(failed.error),
+        _ => Ok(codes),
+    }
+}
+
+/// Same as [`scan_image_with`], also returning the QR codes detected that failed to
+/// decode, from the same pass as the decoded ones. Does not fail if none of them
+/// could be decoded, only if looking for the other symbologies did.
+pub fn scan_image_failed(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> Result<(Vec<DecodedCode>, Vec<FailedGrid>), ScanError> {
+    let (scale, resized) = resize(image, opts);
+    let image = resized.as_ref().unwrap_or(image);
+
+    let (mut codes, mut failed) = match opts.symbology {
+        None | Some(Symbology::Qr) => scan_qr(image, opts),
+        Some(_) => (vec![], vec![]),
+    };
+
+    if opts.symbology != Some(Symbology::Qr) && codes.is_empty() && failed.is_empty() {
+        codes = scan_others(image, opts)?;
+    }
+
+    // Map the corners back to the original pixels
+    for bounds in codes
+        .iter_mut()
+        .map(|code| &mut code.bounds)
+        .chain(failed.iter_mut().map(|failed| &mut failed.bounds))
+    {
+        *bounds = bounds.map(|point| unscale(point, scale));
+    }
+    Ok((codes, failed))
+}
+
+/// Scan for QR codes, retrying on the rotated image if enabled. The failed codes are
+/// those of the pass the codes were decoded in, or of the first one if none was.
+fn scan_qr(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> (Vec<DecodedCode>, Vec<FailedGrid>) {
+    let mut scanned = scan_once(image, opts);
+
+    if opts.rotate && scanned.0.is_empty() {
+        let (width, height) = (image.width() as i32, image.height() as i32);
+
+        for turns in 1..=3 {
+            let rotated = match turns {
+                1 => image.rotate90(),
+                2 => image.rotate180(),
+                _ => image.rotate270(),
+            };
+
+            let (mut codes, mut failed) = scan_once(&rotated, opts);
+            if codes.is_empty() {
+                continue;
+            }
+
+            for bounds in codes
+                .iter_mut()
+                .map(|code| &mut code.bounds)
+                .chain(failed.iter_mut().map(|failed| &mut failed.bounds))
+            {
+                *bounds = bounds.map(|(x, y)| match turns {
+                    1 => (y, height - 1 - x),
+                    2 => (width - 1 - x, height - 1 - y),
+                    _ => (width - 1 - y, x),
+                });
+            }
+            scanned = (codes, failed);
+            break;
+        }
+    }
+
+    scanned
+}
+
+/// Scan for the codes of the given symbology, or of the other two-dimensional ones
+/// than QR codes if unset.
+fn scan_others(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> Result<Vec<DecodedCode>, ScanError> {
+    let formats: HashSet<_> = match opts.symbology {
+        Some(symbology) => HashSet::from([symbology.format()]),
+        None => Symbology::ALL
+            .iter()
+            .filter(|s| **s != Symbology::Qr && !s.is_linear())
+            .map(|s| s.format())
+            .collect(),
+    };
+
+    let mut hints = DecodingHintDictionary::new();
+    hints.insert(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(formats),
+    );
+
+    let luma = luma(image, opts);
+    let (width, height) = luma.dimensions();
+    let results = match rxing::helpers::detect_multiple_in_luma_with_hints(
+        luma.into_raw(),
+        width,
+        height,
+        &mut hints,
+    ) {
+        Ok(results) => results,
+        Err(Exceptions::NotFoundException(_)) => return Ok(vec![]),
+        Err(err) => return Err(ScanError::DecodeFailed(err.into())),
+    };
+
+    let codes = results
+        .iter()
+        .filter_map(|result| {
+            let symbology = Symbology::from_format(result.getBarcodeFormat())?;
+
+            // The bounding box of the points, which may be only two for some codes
+            let points = result.getPoints();
+            let xs = points.iter().map(|point| point.x as i32);
+            let ys = points.iter().map(|point| point.y as i32);
+            let (left, right) = (xs.clone().min()?, xs.max()?);
+            let (top, bottom) = (ys.clone().min()?, ys.max()?);
+
+            Some(DecodedCode {
+                content: result.getText().as_bytes().to_vec(),
+                symbology,
+                encoding: None,
+                version: 0,
+                ecc_level: 0,
+                mask: 0,
+                bounds: [(left, top), (right, top), (right, bottom), (left, bottom)],
+                structured_append: None,
+                modules: vec![],
+                corrections: None,
+            })
+        })
+        .collect();
+
+    Ok(codes)
+}
+
+/// Count the QR codes detected in the image, whether or not they can be decoded,
+/// without retrying on the rotated image. Helps telling why scanning failed.
+pub fn count_grids(image: &DynamicImage, opts: &ScanOptions) -> usize {
+    let (_, resized) = resize(image, opts);
+    let image = resized.as_ref().unwrap_or(image);
+    prepare(image, opts)
+        .detect_grids()
+        .iter()
+        .filter(|grid| plausible(grid, opts))
+        .count()
+}
+
+/// A QR code detected in the image that failed to decode.
+#[derive(Debug)]
+pub struct FailedGrid {
+    /// The corners of the code, as in [`DecodedCode::bounds`].
+    pub bounds: [(i32, i32); 4],
+
+    /// Why the code failed to decode.
+    pub error: ScanError,
+}
+
+/// Decode each QR code detected in the image, in the order they are found, without
+/// retrying on the rotated image or looking for the other symbologies. Unlike
+/// [`scan_image_with`], the codes that fail to decode are kept, to tell how many of
+/// them are damaged.
+pub fn scan_grids(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> Vec<Result<DecodedCode, FailedGrid>> {
+    let (scale, resized) = resize(image, opts);
+    let image = resized.as_ref().unwrap_or(image);
+
+    let mut results = decode_grids(image, opts);
+    for result in results.iter_mut() {
+        let bounds = match result {
+            Ok(code) => &mut code.bounds,
+            Err(failed) => &mut failed.bounds,
+        };
+        *bounds = bounds.map(|point| unscale(point, scale));
+    }
+    results
+}
+
+/// How long each stage of scanning an image for QR codes took.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanProfile {
+    /// Upscaling the image to [`ScanOptions::min_size`], or downscaling it to
+    /// [`ScanOptions::max_dimension`].
+    pub resize: Duration,
+
+    /// Converting the image to grayscale, and inverting it.
+    pub luma: Duration,
+
+    /// Binarizing the grayscale image with [`ScanOptions::threshold`].
+    pub threshold: Duration,
+
+    /// Preparing the image for detecting the QR codes.
+    pub prepare: Duration,
+
+    /// Detecting the QR codes.
+    pub detect: Duration,
+
+    /// Decoding the detected QR codes.
+    pub decode: Duration,
+
+    /// The number of QR codes detected.
+    pub grids: usize,
+
+    /// The number of QR codes decoded.
+    pub decoded: usize,
+}
+
+/// Scan the image for QR codes once, timing each stage, without retrying on the
+/// rotated image or looking for the other symbologies.
+pub fn profile_scan(image: &DynamicImage, opts: &ScanOptions) -> ScanProfile {
+    let start = Instant::now();
+    let (_, resized) = resize(image, opts);
+    let image = resized.as_ref().unwrap_or(image);
+    let resize = start.elapsed();
+
+    let start = Instant::now();
+    let luma = grayscale(image, opts);
+    let luma_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let luma = apply_threshold(luma, opts);
+    let threshold = start.elapsed();
+
+    let start = Instant::now();
+    let mut prepared = rqrr::PreparedImage::prepare(luma);
+    let prepare = start.elapsed();
+
+    let start = Instant::now();
+    let mut grids = prepared.detect_grids();
+    grids.retain(|grid| plausible(grid, opts));
+    let detect = start.elapsed();
+
+    let start = Instant::now();
+    let decoded = grids
+        .iter()
+        .filter(|grid| decode_grid(grid).is_ok())
+        .count();
+    let decode = start.elapsed();
+
+    ScanProfile {
+        resize,
+        luma: luma_elapsed,
+        threshold,
+        prepare,
+        detect,
+        decode,
+        grids: grids.len(),
+        decoded,
+    }
+}
+
+/// Read the version, error correction level, mask and corners of the QR codes
+/// detected in the image from their format information alone, so even of those whose
+/// data fails to decode. The content is left empty, as are the fields read from it.
+pub fn read_metadata(image: &DynamicImage, opts: &ScanOptions) -> Vec<DecodedCode> {
+    let (scale, resized) = resize(image, opts);
+    let image = resized.as_ref().unwrap_or(image);
+
+    prepare(image, opts)
+        .detect_grids()
+        .iter()
+        .filter(|grid| plausible(grid, opts))
+        .filter_map(|grid| grid_metadata(grid, scale))
+        .collect()
+}
+
+fn grid_metadata<G: rqrr::BitGrid>(
+    grid: &rqrr::Grid<G>,
+    scale: f64,
+) -> Option<DecodedCode> {
+    let (ecc_level, mask) = bit_matrix(grid).and_then(format_information).ok()?;
+    Some(DecodedCode {
+        content: vec![],
+        symbology: Symbology::Qr,
+        encoding: None,
+        version: (grid.grid.size() - 17) / 4,
+        ecc_level,
+        mask,
+        bounds: grid.bounds.map(|point| unscale((point.x, point.y), scale)),
+        structured_append: None,
+        modules: grid_modules(grid),
+        corrections: read_data(grid).ok().map(|data| data.corrections),
+    })
+}
+
+/// The scale to resize the image by, and the resized image if it is not 1. Upscaling
+/// is by a whole factor, keeping the modules sharp.
+fn resize(image: &DynamicImage, opts: &ScanOptions) -> (f64, Option<DynamicImage>) {
+    let factor = opts
+        .min_size
+        .map_or(1, |min_size| upscale_factor(image, min_size));
+    let upscaled = Option::zip(
+        image.width().checked_mul(factor),
+        image.height().checked_mul(factor),
+    );
+    match upscaled {
+        Some((width, height)) if factor > 1 => {
+            let upscaled = image.resize_exact(width, height, FilterType::Nearest);
+            return (factor as f64, Some(upscaled));
+        }
+        _ => {}
+    }
+
+    let size = image.width().max(image.height());
+    match opts.max_dimension {
+        Some(max_dimension) if size > max_dimension => {
+            let scale = max_dimension as f64 / size as f64;
+            let width = ((image.width() as f64 * scale).round() as u32).max(1);
+            let height = ((image.height() as f64 * scale).round() as u32).max(1);
+            let downscaled = image.resize_exact(width, height, FilterType::Triangle);
+            (scale, Some(downscaled))
+        }
+        _ => (1.0, None),
+    }
+}
+
+/// The point in the resized image mapped back to the original image.
+fn unscale((x, y): (i32, i32), scale: f64) -> (i32, i32) {
+    ((x as f64 / scale) as i32, (y as f64 / scale) as i32)
+}
+
+fn upscale_factor(image: &DynamicImage, min_size: u32) -> u32 {
+    let size = image.width().min(image.height());
+    if size == 0 || size >= min_size {
+        1
+    } else {
+        min_size.div_ceil(size)
+    }
+}
+
+/// The grayscale image, inverted and binarized as configured. Transparent pixels
+/// count as white.
+fn luma(image: &DynamicImage, opts: &ScanOptions) -> GrayImage {
+    apply_threshold(grayscale(image, opts), opts)
+}
+
+/// The grayscale image, inverted as configured.
+fn grayscale(image: &DynamicImage, opts: &ScanOptions) -> GrayImage {
+    let image = if image.color().has_alpha() {
+        Cow::Owned(DynamicImage::ImageRgba8(on_white(&image.to_rgba8())))
+    } else {
+        Cow::Borrowed(image)
+    };
+
+    let mut luma = image.to_luma8();
+    if opts.invert {
+        image::imageops::invert(&mut luma);
+    }
+    luma
+}
+
+/// The grayscale image, binarized as configured.
+fn apply_threshold(luma: GrayImage, opts: &ScanOptions) -> GrayImage {
+    match opts.threshold {
+        Some(Threshold::Fixed(threshold)) => fixed_threshold(luma, threshold),
+        Some(Threshold::Adaptive) => adaptive_threshold(&luma),
+        None => luma,
+    }
+}
+
+fn prepare(image: &DynamicImage, opts: &ScanOptions) -> rqrr::PreparedImage<GrayImage> {
+    rqrr::PreparedImage::prepare(luma(image, opts))
+}
+
+fn scan_once(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> (Vec<DecodedCode>, Vec<FailedGrid>) {
+    let mut codes = vec![];
+    let mut failed = vec![];
+
+    for result in decode_grids(image, opts) {
+        match result {
+            Ok(code) => codes.push(code),
+            Err(grid) => failed.push(grid),
+        }
+    }
+
+    (codes, failed)
+}
+
+/// Decode each QR code detected in the image, keeping the ones that fail.
+fn decode_grids(
+    image: &DynamicImage,
+    opts: &ScanOptions,
+) -> Vec<Result<DecodedCode, FailedGrid>> {
+    prepare(image, opts)
+        .detect_grids()
+        .iter()
+        .filter(|grid| plausible(grid, opts))
+        .map(|grid| {
+            decode_grid(grid).map_err(|error| FailedGrid {
+                bounds: grid.bounds.map(|point| (point.x, point.y)),
+                error,
+            })
+        })
+        .collect()
+}
+
+/// Whether the detected grid can be a real QR code: within the module range of the
+/// options, and at least a pixel wide per module, as noise makes tiny bogus grids.
+fn plausible<G: rqrr::BitGrid>(grid: &rqrr::Grid<G>, opts: &ScanOptions) -> bool {
+    let modules = grid.grid.size();
+    let corners = grid.bounds;
+    let side = (0..4)
+        .map(|i| {
+            let (a, b) = (corners[i], corners[(i + 1) % 4]);
+            f64::from(a.x - b.x).hypot(f64::from(a.y - b.y))
+        })
+        .fold(f64::INFINITY, f64::min);
+
+    (opts.min_modules..=opts.max_modules).contains(&modules) && side >= modules as f64
+}
+
+fn decode_grid<G: rqrr::BitGrid>(
+    grid: &rqrr::Grid<G>,
+) -> Result<DecodedCode, ScanError> {
+    let mut content = vec![];
+    let meta = match grid.decode_to(&mut content) {
+        Ok(meta) => meta,
+        // rqrr does not support structured append
+        Err(rqrr::DeQRError::UnknownDataType) => return decode_matrix(grid),
+        Err(err) => return Err(ScanError::DecodeFailed(err.into())),
+    };
+
+    // rqrr skips the ECI designators, and does not count the errors it corrects
+    let data = read_data(grid).ok();
+    Ok(DecodedCode {
+        content,
+        symbology: Symbology::Qr,
+        encoding: data
+            .as_ref()
+            .and_then(|data| parse_segments(&data.data, meta.version.0)?.encoding),
+        version: meta.version.0,
+        ecc_level: meta.ecc_level,
+        mask: meta.mask,
+        bounds: grid.bounds.map(|point| (point.x, point.y)),
+        structured_append: None,
+        modules: grid_modules(grid),
+        corrections: data.map(|data| data.corrections),
+    })
+}
+
+/// Decode the grid with rxing, and the data with its segments.
+fn decode_matrix<G: rqrr::BitGrid>(
+    grid: &rqrr::Grid<G>,
+) -> Result<DecodedCode, ScanError> {
+    let data = read_data(grid).map_err(|err| ScanError::DecodeFailed(err.into()))?;
+    let version = (grid.grid.size() - 17) / 4;
+    let segments = parse_segments(&data.data, version)
+        .ok_or_else(|| ScanError::DecodeFailed("unsupported data segments".into()))?;
+
+    Ok(DecodedCode {
+        content: segments.content,
+        symbology: Symbology::Qr,
+        encoding: segments.encoding,
+        version,
+        ecc_level: data.ecc_level,
+        mask: data.mask,
+        bounds: grid.bounds.map(|point| (point.x, point.y)),
+        structured_append: segments.structured_append,
+        modules: grid_modules(grid),
+        corrections: Some(data.corrections),
+    })
+}
+
+/// The data of a QR code, read and error corrected with rxing.
+struct GridData {
+    ecc_level: u16,
+    mask: u16,
+    /// The data codewords, corrected.
+    data: Vec<u8>,
+    corrections: Corrections,
+}
+
+/// Read the data of the grid with rxing, correcting each block and counting its
+/// errors, and retrying on the grid mirrored like rxing's decoder. Done once per
+/// grid, for what rqrr does not give and for the codes it cannot decode.
+fn read_data<G: rqrr::BitGrid>(grid: &rqrr::Grid<G>) -> rxing::common::Result<GridData> {
+    let mut parser = BitMatrixParser::new(bit_matrix(grid)?)?;
+    read_blocks(&mut parser).or_else(|err| {
+        let mut mirrored = || {
+            parser.remask()?;
+            parser.setMirror(true);
+            parser.readVersion()?;
+            parser.readFormatInformation()?;
+            parser.mirror();
+            read_blocks(&mut parser)
+        };
+        mirrored().map_err(|_| err)
+    })
+}
+
+fn read_blocks(parser: &mut BitMatrixParser) -> rxing::common::Result<GridData> {
+    let version = parser.readVersion()?;
+    let format = parser.readFormatInformation()?;
+    let (ec_level, mask) = (format.getErrorCorrectionLevel(), format.getDataMask());
+    let codewords = parser.readCodewords()?;
+
+    let field = get_predefined_genericgf(PredefinedGenericGF::QrCodeField256);
+    let decoder = ReedSolomonDecoder::new(field);
+    let mut data = vec![];
+    let mut corrections = Corrections {
+        errors: 0,
+        max_errors: 0,
+        block_errors: 0,
+        block_max_errors: 0,
+    };
+    for block in DataBlock::getDataBlocks(&codewords, version, ec_level)? {
+        let mut received: Vec<i32> =
+            block.getCodewords().iter().map(|&b| b.into()).collect();
+        let data_codewords = block.getNumDataCodewords() as usize;
+        let ec_codewords = received.len() - data_codewords;
+        let (errors, max_errors) = (
+            decoder.decode(&mut received, ec_codewords as i32)?,
+            ec_codewords / 2,
+        );
+        corrections.errors += errors;
+        corrections.max_errors += max_errors;
+        if corrections.block_max_errors == 0
+            || errors * corrections.block_max_errors
+                > corrections.block_errors * max_errors
+        {
+            corrections.block_errors = errors;
+            corrections.block_max_errors = max_errors;
+        }
+        data.extend(received[..data_codewords].iter().map(|&b| b as u8));
+    }
+
+    Ok(GridData {
+        ecc_level: ec_level.get_value() as u16,
+        mask: mask as u16,
+        data,
+        corrections,
+    })
+}
+
+/// The modules of the grid, row by row.
+fn grid_modules<G: rqrr::BitGrid>(grid: &rqrr::Grid<G>) -> Vec<Vec<bool>> {
+    let size = grid.grid.size();
+    (0..size)
+        .map(|y| (0..size).map(|x| grid.grid.bit(y, x)).collect())
+        .collect()
+}
+
+/// The modules of the grid, as an rxing bit matrix.
+fn bit_matrix<G: rqrr::BitGrid>(
+    grid: &rqrr::Grid<G>,
+) -> rxing::common::Result<BitMatrix> {
+    let size = grid.grid.size();
+    let mut bits = BitMatrix::new(size as u32, size as u32)?;
+    for y in 0..size {
+        for x in 0..size {
+            if grid.grid.bit(y, x) {
+                bits.set(x as u32, y as u32);
+            }
+        }
+    }
+    Ok(bits)
+}
+
+/// The error correction level and the mask of the QR code.
+fn format_information(bits: BitMatrix) -> rxing::common::Result<(u16, u16)> {
+    let mut parser = BitMatrixParser::new(bits)?;
+    let format = parser.readFormatInformation()?;
+    let ecc_level = format.getErrorCorrectionLevel().get_value() as u16;
+    Ok((ecc_level, format.getDataMask() as u16))
+}
+
+/// Reads the data codewords of a QR code bit by bit.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn take(&mut self, count: usize) -> Option<u32> {
+        if self.pos + count > self.bytes.len() * 8 {
+            return None;
+        }
+
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = self.bytes[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            value = value << 1 | bit as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.pos += count;
+        (self.pos <= self.bytes.len() * 8).then_some(())
+    }
+}
+
+/// The decoded data segments of a QR code.
+struct Segments {
+    content: Vec<u8>,
+    encoding: Option<&'static Encoding>,
+    structured_append: Option<StructuredAppend>,
+}
+
+/// The characters of alphanumeric mode.
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Decode the segments of the data. The encoding is declared by the first ECI
+/// designator, or implied by a Kanji segment.
+fn parse_segments(data: &[u8], version: usize) -> Option<Segments> {
+    // The width of the character counts depends on the version
+    let size = match version {
+        0..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+
+    let mut bits = BitReader {
+        bytes: data,
+        pos: 0,
+    };
+    let mut content = vec![];
+    let mut eci = None;
+    let mut kanji = false;
+    let mut structured_append = None;
+
+    while let Some(mode) = bits.take(4) {
+        match mode {
+            0b0000 => break,
+            0b0111 => {
+                let first = bits.take(8)?;
+                let designator = if first & 0x80 == 0 {
+                    first
+                } else if first & 0xc0 == 0x80 {
+                    (first & 0x3f) << 8 | bits.take(8)?
+                } else {
+                    (first & 0x1f) << 16 | bits.take(16)?
+                };
+                eci.get_or_insert(eci_encoding(designator));
+            }
+            0b0001 => {
+                let count = bits.take([10, 12, 14][size])? as usize;
+                for digits in (0..count).step_by(3) {
+                    let (len, width) = match count - digits {
+                        1 => (1, 4),
+                        2 => (2, 7),
+                        _ => (3, 10),
+                    };
+                    let number = bits.take(width)?;
+                    content.extend(format!("{number:0len$}").bytes());
+                }
+            }
+            0b0010 => {
+                let count = bits.take([9, 11, 13][size])? as usize;
+                for chars in (0..count).step_by(2) {
+                    if count - chars == 1 {
+                        content.push(*ALPHANUMERIC.get(bits.take(6)? as usize)?);
+                    } else {
+                        let pair = bits.take(11)? as usize;
+                        content.push(*ALPHANUMERIC.get(pair / 45)?);
+                        content.push(ALPHANUMERIC[pair % 45]);
+                    }
+                }
+            }
+            0b0100 => {
+                let count = bits.take([8, 16, 16][size])? as usize;
+                for _ in 0..count {
+                    content.push(bits.take(8)? as u8);
+                }
+            }
+            0b1000 => {
+                let count = bits.take([8, 10, 12][size])? as usize;
+                for _ in 0..count {
+                    // Back to Shift_JIS, as rqrr does
+                    let value = bits.take(13)?;
+                    let value = ((value / 0xc0) << 8) | (value % 0xc0);
+                    let value = if value + 0x8140 <= 0x9ffc {
+                        value + 0x8140
+                    } else {
+                        value + 0xc140
+                    };
+                    content.extend([(value >> 8) as u8, value as u8]);
+                }
+                kanji = true;
+            }
+            0b0011 => {
+                structured_append.get_or_insert(StructuredAppend {
+                    index: bits.take(4)? as u8,
+                    total: bits.take(4)? as u8 + 1,
+                    parity: bits.take(8)? as u8,
+                });
+            }
+            // FNC1 in second position
+            0b1001 => bits.skip(8)?,
+            // FNC1 in first position
+            0b0101 => {}
+            _ => return None,
+        }
+    }
+
+    let encoding = eci.unwrap_or(kanji.then_some(encoding_rs::SHIFT_JIS));
+    Some(Segments {
+        content,
+        encoding,
+        structured_append,
+    })
+}
+
+/// The character encoding of the ECI designator, if supported.
+fn eci_encoding(eci: u32) -> Option<&'static Encoding> {
+    use encoding_rs::*;
+
+    let encoding = match eci {
+        1 | 3 | 23 | 27 => WINDOWS_1252,
+        4 => ISO_8859_2,
+        5 => ISO_8859_3,
+        6 => ISO_8859_4,
+        7 => ISO_8859_5,
+        8 => ISO_8859_6,
+        9 => ISO_8859_7,
+        10 => ISO_8859_8,
+        11 => WINDOWS_1254,
+        12 => ISO_8859_10,
+        13 => WINDOWS_874,
+        15 => ISO_8859_13,
+        16 => ISO_8859_14,
+        17 => ISO_8859_15,
+        18 => ISO_8859_16,
+        20 => SHIFT_JIS,
+        21 => WINDOWS_1250,
+        22 => WINDOWS_1251,
+        24 => WINDOWS_1256,
+        25 => UTF_16BE,
+        26 => UTF_8,
+        28 => BIG5,
+        29 => GB18030,
+        30 => EUC_KR,
+        _ => return None,
+    };
+    Some(encoding)
+}
+
+fn binarize(bright: bool) -> Luma<u8> {
+    if bright {
+        Luma([u8::MAX])
+    } else {
+        Luma([0])
+    }
+}
+
+fn fixed_threshold(mut image: GrayImage, threshold: u8) -> GrayImage {
+    for pixel in image.pixels_mut() {
+        *pixel = binarize(pixel.0[0] > threshold);
+    }
+    image
+}
+
+fn adaptive_threshold(image: &GrayImage) -> GrayImage {
+    // How much darker than the local mean a pixel must be to become black, so that
+    // noise in flat regions doesn't turn into speckles
+    const OFFSET: u64 = 8;
+
+    let (width, height) = image.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    let radius = (width.max(height) / 16).max(8) as usize;
+
+    // Summed-area table, with an extra row and column of zeros
+    let mut sums = vec![0u64; (w + 1) * (h + 1)];
+    for y in 0..h {
+        let mut row = 0;
+        for x in 0..w {
+            row += image.get_pixel(x as u32, y as u32).0[0] as u64;
+            sums[(y + 1) * (w + 1) + x + 1] = sums[y * (w + 1) + x + 1] + row;
+        }
+    }
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (x1, y1) = ((x + radius + 1).min(w), (y + radius + 1).min(h));
+
+        let area = ((x1 - x0) * (y1 - y0)) as u64;
+        let sum = sums[y1 * (w + 1) + x1] + sums[y0 * (w + 1) + x0]
+            - sums[y0 * (w + 1) + x1]
+            - sums[y1 * (w + 1) + x0];
+
+        let value = image.get_pixel(x as u32, y as u32).0[0] as u64;
+        binarize(value * area + OFFSET * area > sum)
+    })
+}